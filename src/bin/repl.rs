@@ -1,5 +1,16 @@
-use std::io;
-use std::io::Write;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+use std::time::Instant;
+
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
 
 use risp::*;
 
@@ -7,47 +18,341 @@ use risp::*;
 use risp::comms::*;
 
 #[cfg(feature = "comms-rs")]
-fn repl_env<'a>() -> RispEnv<'a> {
+fn repl_env() -> RispEnv {
     comms_env()
 }
 
 #[cfg(not(feature = "comms-rs"))]
-fn repl_env<'a>() -> RispEnv<'a> {
+fn repl_env() -> RispEnv {
     standard_env()
 }
 
-fn main() {
-    let mut env = repl_env();
+/// Line editor hooks, completing symbol names from the live environment.
+struct RispHelper {
+    env: RispEnv,
+}
 
-    loop {
-        print!("risp > ");
-        io::stdout().flush().expect("failed to flush stdout");
+impl Completer for RispHelper {
+    type Candidate = String;
 
-        let mut expr_str = String::new();
-        let nbytes = io::stdin().read_line(&mut expr_str).expect("failed to read line");
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<String>)> {
+        let start = word_start(line, pos);
+        let prefix = &line[start..pos];
+        let candidates = self.env
+            .symbols()
+            .into_iter()
+            .filter(|s| s.starts_with(prefix))
+            .collect();
+        Ok((start, candidates))
+    }
+}
 
-        if nbytes == 0 {
-            // EOF on empty line means ctrl + d was hit, so bail
-            println!("");
-            break;
+/// Where the symbol under the cursor starts, after the last delimiter or
+/// quote shorthand (`'`, `` ` ``, `,` and `,@`).
+fn word_start(line: &str, pos: usize) -> usize {
+    // Delimiters may be multi-byte, e.g. a non-breaking space
+    let start = line[..pos]
+        .char_indices()
+        .rev()
+        .find(|(_, c)| c.is_whitespace() || "()\"'`,".contains(*c))
+        .map(|(i, c)| i + c.len_utf8())
+        .unwrap_or(0);
+    if line[..start].ends_with(',') && line[start..pos].starts_with('@') {
+        start + 1
+    } else {
+        start
+    }
+}
+
+impl Hinter for RispHelper {
+    type Hint = String;
+}
+
+impl Highlighter for RispHelper {}
+
+impl Validator for RispHelper {}
+
+impl Helper for RispHelper {}
+
+/// `~/.risp_history`, if we can tell where home is.
+fn history_path() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .map(|home| PathBuf::from(home).join(".risp_history"))
+}
+
+/// A result pretty printed to the terminal width, `$COLUMNS` if the shell
+/// exports it.
+fn format_result(re: &RispExp) -> String {
+    let width = env::var("COLUMNS").ok().and_then(|c| c.parse().ok()).unwrap_or(80);
+    re.pretty(width)
+}
+
+fn print_result(re: RispExp) {
+    println!("{}", format_result(&re));
+}
+
+/// Evaluate every form in `source` in order, handing each value to
+/// `on_value`. Stops at the first error and returns it rendered against the
+/// source, with `file` as the location prefix.
+fn eval_source(
+    source: &str,
+    file: &str,
+    env: &mut RispEnv,
+    mut on_value: impl FnMut(RispExp),
+) -> Result<(), String> {
+    let forms = parse_all_spanned(source).map_err(|rerr| render_error(&rerr, file, source, &[]))?;
+    let maps: Vec<_> = forms.into_iter().map(|(_, map)| map).collect();
+
+    // Evaluate through the maps so errors point at the exact form
+    for map in &maps {
+        match map.eval(env) {
+            Ok(re) => on_value(re),
+            Err(rerr) => return Err(render_error(&rerr, file, source, &maps)),
         }
+    }
+    Ok(())
+}
+
+/// Run the script at `path` non-interactively, binding `script_args` to the
+/// `args` variable as a list of strings. Exits with a non-zero status on the
+/// first error.
+fn run_script(path: &str, script_args: &[String]) {
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            process::exit(1);
+        },
+    };
+
+    let mut env = repl_env();
+    let args: Vec<_> = script_args.iter().map(|a| RispExp::Str(a.clone())).collect();
+    env.define_variable("args", &RispExp::List(args));
+
+    if let Err(msg) = eval_source(&source, path, &mut env, |_| {}) {
+        eprintln!("{}", msg);
+        process::exit(1);
+    }
+}
+
+const COMMANDS_HELP: &str = "\
+:load <file>   evaluate a file in the current environment
+:env           list every bound symbol
+:reset         start over with a fresh environment
+:time <exp>    evaluate an expression and report how long it took
+:type <exp>    show the type of an expression's value
+:help [name]   show this message, or help for a builtin
+exit           leave the REPL";
+
+/// Handle a `:command` line. Returns what to show, or a message if the
+/// command couldn't be run.
+fn run_command(line: &str, env: &mut RispEnv) -> Result<String, String> {
+    let line = line.trim();
+    let (command, arg) = match line.find(char::is_whitespace) {
+        Some(i) => (&line[..i], line[i..].trim()),
+        None => (line, ""),
+    };
+
+    match command {
+        ":load" => {
+            if arg.is_empty() {
+                return Err("usage: :load <file>".to_string());
+            }
+            let source = fs::read_to_string(arg).map_err(|e| format!("{}: {}", arg, e))?;
+            eval_source(&source, arg, env, |_| {})?;
+            Ok(format!("loaded {}", arg))
+        },
+        ":env" => {
+            let mut lines = vec![];
+            let mut line = String::new();
+            for symbol in env.symbols() {
+                if !line.is_empty() && line.len() + symbol.len() >= 80 {
+                    lines.push(line.trim_end().to_string());
+                    line.clear();
+                }
+                line.push_str(&symbol);
+                line.push(' ');
+            }
+            lines.push(line.trim_end().to_string());
+            Ok(lines.join("\n"))
+        },
+        ":reset" => {
+            // Break the old environment's lambda cycles so it's freed
+            env.clear();
+            *env = repl_env();
+            Ok("environment reset".to_string())
+        },
+        ":time" => {
+            let mut lines = vec![];
+            let start = Instant::now();
+            eval_source(arg, "<repl>", env, |re| lines.push(format_result(&re)))?;
+            lines.push(format!("elapsed: {:?}", start.elapsed()));
+            Ok(lines.join("\n"))
+        },
+        ":type" => {
+            let mut types = vec![];
+            eval_source(arg, "<repl>", env, |re| types.push(re.type_name()))?;
+            Ok(types.join("\n"))
+        },
+        ":help" => {
+            if arg.is_empty() {
+                Ok(COMMANDS_HELP.to_string())
+            } else if let Some(RispExp::Lambda(l)) = env.get(arg) {
+                Ok(format!("({} {}) user defined lambda", arg, l.params.join(" ")))
+            } else {
+                env.help(arg).ok_or_else(|| format!("no help for `{}`", arg))
+            }
+        },
+        _ => Err(format!("unknown command `{}`, try :help", command)),
+    }
+}
+
+fn main() {
+    // `repl path/to/script.risp [args...]` runs a script instead
+    let cli_args: Vec<String> = env::args().collect();
+    if let Some(path) = cli_args.get(1) {
+        run_script(path, &cli_args[2..]);
+        return;
+    }
+
+    let mut env = repl_env();
+
+    let mut rl: Editor<RispHelper, DefaultHistory> = Editor::new().expect("failed to create line editor");
+    rl.set_helper(Some(RispHelper { env: env.clone() }));
+    let history = history_path();
+    if let Some(path) = &history {
+        // No history yet is fine
+        let _ = rl.load_history(path);
+    }
+
+    loop {
+        let mut expr_str = match rl.readline("risp > ") {
+            Ok(line) => line,
+            // Ctrl + c drops the current line
+            Err(ReadlineError::Interrupted) => continue,
+            // Ctrl + d on an empty line, so bail
+            Err(ReadlineError::Eof) => break,
+            Err(e) => {
+                eprintln!("{}", e);
+                break;
+            },
+        };
 
         // Handle some REPL type things first
-        let trimmed = &expr_str.trim_end();
+        let trimmed = expr_str.trim();
         if "exit".eq_ignore_ascii_case(trimmed) {
             // Bail when user types "exit"
             break;
-        } else if "".eq_ignore_ascii_case(trimmed) {
+        } else if trimmed.is_empty() {
             // Handle empty line by restarting loop
             continue;
         }
 
+        // Keep reading until parentheses, strings and comments are closed
+        let mut interrupted = false;
+        while is_incomplete(&expr_str) {
+            match rl.readline("  ... > ") {
+                Ok(line) => {
+                    expr_str.push('\n');
+                    expr_str.push_str(&line);
+                },
+                Err(ReadlineError::Interrupted) => {
+                    interrupted = true;
+                    break;
+                },
+                // EOF, let the parser report what's missing
+                Err(_) => break,
+            }
+        }
+        if interrupted {
+            continue;
+        }
+        let _ = rl.add_history_entry(expr_str.as_str());
+
+        if expr_str.starts_with(':') {
+            match run_command(&expr_str, &mut env) {
+                Ok(output) | Err(output) => println!("{}", output),
+            }
+            // `:reset` swaps the environment out from under the completer
+            if let Some(helper) = rl.helper_mut() {
+                helper.env = env.clone();
+            }
+            continue;
+        }
+
         // Now try to treat it as risp code
-        let expr = parse(expr_str.as_str()).expect("failed to parse line");
+        if let Err(msg) = eval_source(&expr_str, "<repl>", &mut env, print_result) {
+            println!("{}", msg);
+        }
+    }
+
+    if let Some(path) = &history {
+        if let Err(e) = rl.save_history(path) {
+            eprintln!("failed to save history to {}: {}", path.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_command() {
+        let mut env = repl_env();
+        assert_eq!(run_command(":help", &mut env), Ok(COMMANDS_HELP.to_string()));
+        assert_eq!(run_command(":help car", &mut env), Ok("(car list) or (first list), the first element of `list`".to_string()));
+        assert_eq!(run_command(":help nope", &mut env), Err("no help for `nope`".to_string()));
+        assert_eq!(run_command(":nope", &mut env), Err("unknown command `:nope`, try :help".to_string()));
+
+        // Help registered along with a builtin, and lambdas
+        env.register_fn("double", |x: f64| x * 2.0);
+        env.set_help("double", "(double x) twice `x`");
+        assert_eq!(run_command(":help double", &mut env), Ok("(double x) twice `x`".to_string()));
+        eval_source("(let add (fn (a b) (+ a b)))", "<test>", &mut env, |_| {}).expect("failed to eval");
+        assert_eq!(run_command(":help add", &mut env), Ok("(add a b) user defined lambda".to_string()));
+
+        assert_eq!(run_command(":type 1 \"a\" (add 1 2)", &mut env), Ok("number\nstring\nnumber".to_string()));
+        let output = run_command(":time (add 1 2)", &mut env).expect("failed to time");
+        assert!(output.starts_with("3\nelapsed: "), "{}", output);
+        let err = run_command(":type (add 1)", &mut env).expect_err("should fail");
+        assert!(err.starts_with("<repl>:1:1: "), "{}", err);
+
+        let output = run_command(":env", &mut env).expect("failed to list");
+        assert!(output.lines().all(|l| l.len() < 80), "{}", output);
+        assert!(output.split_whitespace().any(|s| s == "double"), "{}", output);
+
+        assert_eq!(run_command(":reset", &mut env), Ok("environment reset".to_string()));
+        assert_eq!(env.get("add"), None);
+        assert_eq!(env.get("double"), None);
+        assert!(env.get("car").is_some());
+    }
+
+    #[test]
+    fn test_run_command_load() {
+        let path = std::env::temp_dir().join("risp_test_run_command_load.risp");
+        std::fs::write(&path, "(let loaded 1)\n(+ loaded \"a\")\n").expect("failed to write script");
+        let path = path.to_str().expect("non utf-8 temp dir").to_string();
+
+        let mut env = repl_env();
+        let err = run_command(&format!(":load {}", path), &mut env).expect_err("should fail");
+        assert!(err.starts_with(&format!("{}:2:1: ", path)), "{}", err);
+        assert_eq!(env.get("loaded"), Some(RispExp::Number(1.0)));
+
+        std::fs::write(&path, "(let loaded 2)\n").expect("failed to write script");
+        assert_eq!(run_command(&format!(":load {}", path), &mut env), Ok(format!("loaded {}", path)));
+        assert_eq!(env.get("loaded"), Some(RispExp::Number(2.0)));
+        std::fs::remove_file(&path).expect("failed to remove script");
+
+        assert_eq!(run_command(":load", &mut env), Err("usage: :load <file>".to_string()));
+        assert!(run_command(":load /no/such/file.risp", &mut env).is_err());
+    }
 
-        match eval(expr, &mut env) {
-            Ok(re) => println!("{}", re),
-            Err(rerr) => println!("{}", rerr),
+    #[test]
+    fn test_word_start() {
+        for (line, start) in [("ca", 0), ("(+ 1 ca", 5), ("'ca", 1), ("`(a ,fo", 5), ("(f ,@fo", 5), ("\"a b", 3), ("(car\u{a0}ca", 6)].iter() {
+            assert_eq!(word_start(line, line.len()), *start, "{}", line);
         }
     }
 }
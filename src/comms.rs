@@ -1,7 +1,7 @@
 use std::fmt::Debug;
 use std::sync::{Arc, Mutex};
 
-use crate::{eval, eval_to_number, RispErr, RispExp, RispFunc, standard_env, RispEnv};
+use crate::{eval, eval_to_number, Arity, RispErr, RispExp, RispFunc, standard_env, RispEnv};
 
 use comms_rs::prelude::*;
 use comms_rs::node::graph::Graph;
@@ -83,9 +83,7 @@ where
 
 pub fn comms_qpsk(args: &[RispExp], env: &mut RispEnv) -> Result<RispExp, RispErr> {
     // 1 param: output node
-    if args.len() != 1 {
-        return Err(RispErr::Reason("`qpsk` expects 1 argument".to_string()));
-    }
+    Arity::Exactly(1).check("qpsk", args)?;
 
     let mut graph = env.comms_graphs[0].lock().expect("failed to lock Graph");
     graph.add_node(Arc::new(Mutex::new(QpskMod::new())));
@@ -93,9 +91,15 @@ pub fn comms_qpsk(args: &[RispExp], env: &mut RispEnv) -> Result<RispExp, RispEr
     Err(RispErr::Reason("not implemented".to_string()))
 }
 
-pub fn comms_env<'a>() -> RispEnv<'a> {
-    let mut env = standard_env();
+/// Give `env` a graph to build and register the comms-rs nodes.
+pub fn comms_builtins(env: &mut RispEnv) {
     env.comms_graphs.push(Arc::new(Mutex::new(Graph::new(None))));
     env.define_procedure("qpsk", comms_qpsk as RispFunc);
+    env.set_help("qpsk", "(qpsk node) add a QPSK modulator to the graph");
+}
+
+pub fn comms_env() -> RispEnv {
+    let mut env = standard_env();
+    comms_builtins(&mut env);
     env
 }
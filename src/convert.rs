@@ -0,0 +1,264 @@
+//! Conversions between risp values and Rust types, so native functions can be
+//! registered with plain Rust signatures via `RispEnv::register_fn`.
+
+use crate::{Arity, RispErr, RispExp};
+
+/// Build a Rust value out of a risp value, or explain why it doesn't fit.
+pub trait FromRisp: Sized {
+    fn from_risp(exp: &RispExp) -> Result<Self, RispErr>;
+}
+
+/// Turn a Rust value into a risp value.
+pub trait IntoRisp {
+    fn into_risp(self) -> RispExp;
+}
+
+/// What a registered function may return: any `IntoRisp` value, or a
+/// `Result` of one when the function can fail.
+pub trait IntoRispResult {
+    fn into_risp_result(self) -> Result<RispExp, RispErr>;
+}
+
+impl<T: IntoRisp> IntoRispResult for T {
+    fn into_risp_result(self) -> Result<RispExp, RispErr> {
+        Ok(self.into_risp())
+    }
+}
+
+impl<T: IntoRisp> IntoRispResult for Result<T, RispErr> {
+    fn into_risp_result(self) -> Result<RispExp, RispErr> {
+        self.map(IntoRisp::into_risp)
+    }
+}
+
+impl FromRisp for RispExp {
+    fn from_risp(exp: &RispExp) -> Result<Self, RispErr> {
+        Ok(exp.clone())
+    }
+}
+
+impl IntoRisp for RispExp {
+    fn into_risp(self) -> RispExp {
+        self
+    }
+}
+
+impl FromRisp for f64 {
+    fn from_risp(exp: &RispExp) -> Result<Self, RispErr> {
+        exp.as_number()
+    }
+}
+
+impl IntoRisp for f64 {
+    fn into_risp(self) -> RispExp {
+        RispExp::Number(self)
+    }
+}
+
+impl FromRisp for bool {
+    fn from_risp(exp: &RispExp) -> Result<Self, RispErr> {
+        match exp {
+            RispExp::Bool(b) => Ok(*b),
+            other => Err(RispErr::type_error("a boolean", other)),
+        }
+    }
+}
+
+impl IntoRisp for bool {
+    fn into_risp(self) -> RispExp {
+        RispExp::Bool(self)
+    }
+}
+
+impl FromRisp for String {
+    fn from_risp(exp: &RispExp) -> Result<Self, RispErr> {
+        Ok(exp.as_str()?.to_string())
+    }
+}
+
+impl IntoRisp for String {
+    fn into_risp(self) -> RispExp {
+        RispExp::Str(self)
+    }
+}
+
+impl IntoRisp for &str {
+    fn into_risp(self) -> RispExp {
+        RispExp::Str(self.to_string())
+    }
+}
+
+/// Functions called only for their effect return the empty list.
+impl IntoRisp for () {
+    fn into_risp(self) -> RispExp {
+        RispExp::List(vec![])
+    }
+}
+
+impl<T: FromRisp> FromRisp for Vec<T> {
+    fn from_risp(exp: &RispExp) -> Result<Self, RispErr> {
+        match exp {
+            RispExp::List(v) => v.iter().map(T::from_risp).collect(),
+            other => Err(RispErr::type_error("a list", other)),
+        }
+    }
+}
+
+impl<T: IntoRisp> IntoRisp for Vec<T> {
+    fn into_risp(self) -> RispExp {
+        RispExp::List(self.into_iter().map(IntoRisp::into_risp).collect())
+    }
+}
+
+/// `None` is the empty list, anything else is converted as a `T`.
+impl<T: FromRisp> FromRisp for Option<T> {
+    fn from_risp(exp: &RispExp) -> Result<Self, RispErr> {
+        match exp {
+            RispExp::List(v) if v.is_empty() => Ok(None),
+            other => T::from_risp(other).map(Some),
+        }
+    }
+}
+
+impl<T: IntoRisp> IntoRisp for Option<T> {
+    fn into_risp(self) -> RispExp {
+        match self {
+            Some(x) => x.into_risp(),
+            None => RispExp::List(vec![]),
+        }
+    }
+}
+
+// Tuples are fixed length lists
+macro_rules! impl_tuple {
+    ($len:expr, $expected:expr; $($t:ident $v:ident),+) => {
+        impl<$($t: FromRisp),+> FromRisp for ($($t,)+) {
+            fn from_risp(exp: &RispExp) -> Result<Self, RispErr> {
+                match exp {
+                    RispExp::List(items) if items.len() == $len => {
+                        let [$($v),+] = items.as_slice() else {
+                            unreachable!("length checked above")
+                        };
+                        Ok(($($t::from_risp($v)?,)+))
+                    },
+                    other => Err(RispErr::type_error($expected, other)),
+                }
+            }
+        }
+
+        impl<$($t: IntoRisp),+> IntoRisp for ($($t,)+) {
+            fn into_risp(self) -> RispExp {
+                let ($($v,)+) = self;
+                RispExp::List(vec![$($v.into_risp()),+])
+            }
+        }
+    };
+}
+
+impl_tuple!(2, "a list of 2 values"; A a, B b);
+impl_tuple!(3, "a list of 3 values"; A a, B b, C c);
+impl_tuple!(4, "a list of 4 values"; A a, B b, C c, D d);
+
+/// A Rust function that can be called with risp arguments. `Args` is the
+/// tuple of parameter types, it only exists to tell the impls apart.
+pub trait RispFn<Args> {
+    fn call(&self, name: &str, args: &[RispExp]) -> Result<RispExp, RispErr>;
+}
+
+macro_rules! impl_risp_fn {
+    ($len:expr; $($t:ident $v:ident),*) => {
+        impl<F, R, $($t),*> RispFn<($($t,)*)> for F
+        where
+            F: Fn($($t),*) -> R,
+            R: IntoRispResult,
+            $($t: FromRisp,)*
+        {
+            fn call(&self, name: &str, args: &[RispExp]) -> Result<RispExp, RispErr> {
+                let [$($v),*] = args else {
+                    return Err(RispErr::Arity {
+                        name: name.to_string(),
+                        expected: Arity::Exactly($len),
+                        found: args.len(),
+                    });
+                };
+                self($($t::from_risp($v)?),*).into_risp_result()
+            }
+        }
+    };
+}
+
+impl_risp_fn!(0;);
+impl_risp_fn!(1; A a);
+impl_risp_fn!(2; A a, B b);
+impl_risp_fn!(3; A a, B b, C c);
+impl_risp_fn!(4; A a, B b, C c, D d);
+impl_risp_fn!(5; A a, B b, C c, D d, E e);
+impl_risp_fn!(6; A a, B b, C c, D d, E e, G g);
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn test_conversions() {
+        let exp = RispExp::List(vec![RispExp::Number(1.0), RispExp::Number(2.0)]);
+        assert_eq!(Vec::<f64>::from_risp(&exp), Ok(vec![1.0, 2.0]));
+        assert_eq!(<(f64, f64)>::from_risp(&exp), Ok((1.0, 2.0)));
+        assert_eq!(
+            <(f64, f64, f64)>::from_risp(&exp),
+            Err(RispErr::type_error("a list of 3 values", &exp))
+        );
+        assert_eq!(
+            String::from_risp(&RispExp::Number(1.0)),
+            Err(RispErr::type_error("a string", &RispExp::Number(1.0)))
+        );
+
+        assert_eq!(Option::<f64>::from_risp(&RispExp::List(vec![])), Ok(None));
+        assert_eq!(Option::<f64>::from_risp(&RispExp::Number(3.0)), Ok(Some(3.0)));
+        assert_eq!(None::<f64>.into_risp(), RispExp::List(vec![]));
+
+        let exp = (String::from("a"), true).into_risp();
+        assert_eq!(exp, RispExp::List(vec![RispExp::Str("a".to_string()), RispExp::Bool(true)]));
+        assert_eq!(<(String, bool)>::from_risp(&exp), Ok(("a".to_string(), true)));
+    }
+
+    #[test]
+    fn test_register_fn() {
+        let mut env = standard_env();
+        env.register_fn("hypot", |a: f64, b: f64| a.hypot(b));
+        env.register_fn("repeat", |s: String, n: f64| s.repeat(n as usize));
+        env.register_fn("sum", |xs: Vec<f64>| xs.iter().sum::<f64>());
+        env.register_fn("checked-sqrt", |x: f64| {
+            if x < 0.0 {
+                Err(RispErr::Reason("negative".to_string()))
+            } else {
+                Ok(x.sqrt())
+            }
+        });
+
+        let expr = "(hypot 3 4)";
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, RispExp::Number(5.0));
+
+        let expr = "(repeat \"ab\" 3)";
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, RispExp::Str("ababab".to_string()));
+
+        let expr = "(sum (string-split \"1 2\" \"\"))";
+        let err = eval(parse(expr).expect("failed to parse"), &mut env).unwrap_err();
+        assert_eq!(err, RispErr::type_error("a number", &RispExp::Str("1".to_string())));
+
+        let expr = "(checked-sqrt -1)";
+        let err = eval(parse(expr).expect("failed to parse"), &mut env).unwrap_err();
+        assert_eq!(err, RispErr::Reason("negative".to_string()));
+
+        // Arity and type errors come from the signature
+        let expr = "(hypot 3)";
+        let err = eval(parse(expr).expect("failed to parse"), &mut env).unwrap_err();
+        assert_eq!(err, RispErr::Arity { name: "hypot".to_string(), expected: Arity::Exactly(2), found: 1 });
+
+        let expr = "(hypot 3 \"4\")";
+        let err = eval(parse(expr).expect("failed to parse"), &mut env).unwrap_err();
+        assert_eq!(err, RispErr::type_error("a number", &RispExp::Str("4".to_string())));
+    }
+}
@@ -1,45 +1,172 @@
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
 use std::f64;
-use std::sync::{Arc, Mutex};
-use crate::{eval, eval_to_number, RispErr, RispExp};
+use std::fmt;
+use std::rc::Rc;
+use crate::{apply, eval_ref, format_number, Arity, RispErr, RispExp, RispFn, RispLambda, RispProc};
 
 pub type RispFunc = fn(&[RispExp], &mut RispEnv) -> Result<RispExp, RispErr>;
 
+/// A builtin that may capture Rust state, e.g. a connection or a counter.
+pub type RispClosure = Rc<dyn Fn(&[RispExp], &mut RispEnv) -> Result<RispExp, RispErr>>;
+
+#[cfg(feature = "comms-rs")]
+use std::sync::{Arc, Mutex};
 #[cfg(feature = "comms-rs")]
 use comms_rs::node::graph::Graph;
 
+/// A scope of bindings, optionally nested inside an outer scope.
+///
+/// Frames are reference counted, so cloning a `RispEnv` gives another handle
+/// to the same scope rather than a copy of it. That lets lambdas hold on to
+/// the scope they were defined in after the defining call has returned.
 #[derive(Clone)]
-pub struct RispEnv<'a> {
-    data: HashMap<String, RispExp>,
-    funcs: HashMap<String, RispFunc>,
-    pub outer: Option<&'a RispEnv<'a>>,
+pub struct RispEnv {
+    data: Rc<RefCell<HashMap<String, RispExp>>>,
+    funcs: Rc<RefCell<HashMap<String, RispFunc>>>,
+    /// Usage summaries for the builtins bound in this scope.
+    help: Rc<RefCell<HashMap<String, String>>>,
+    outer: Option<Rc<RispEnv>>,
+    /// Shared by every scope nested inside the one that created it.
+    lenient_symbols: Rc<Cell<bool>>,
 
     #[cfg(feature = "comms-rs")]
     pub comms_graphs: Vec<Arc<Mutex<Graph>>>,
 }
 
-impl<'a> RispEnv<'a> {
+impl RispEnv {
     pub fn new() -> Self {
         Self {
-            data: HashMap::new(),
-            funcs: HashMap::new(),
+            data: Rc::new(RefCell::new(HashMap::new())),
+            funcs: Rc::new(RefCell::new(HashMap::new())),
+            help: Rc::new(RefCell::new(HashMap::new())),
             outer: None,
+            lenient_symbols: Rc::new(Cell::new(false)),
             #[cfg(feature = "comms-rs")]
             comms_graphs: vec![],
         }
     }
 
+    /// Create an empty scope nested inside `outer`.
+    pub fn with_outer(outer: &RispEnv) -> Self {
+        Self {
+            data: Rc::new(RefCell::new(HashMap::new())),
+            funcs: Rc::new(RefCell::new(HashMap::new())),
+            help: Rc::new(RefCell::new(HashMap::new())),
+            outer: Some(Rc::new(outer.clone())),
+            lenient_symbols: outer.lenient_symbols.clone(),
+            #[cfg(feature = "comms-rs")]
+            comms_graphs: outer.comms_graphs.clone(),
+        }
+    }
+
+    pub fn outer(&self) -> Option<&RispEnv> {
+        self.outer.as_deref()
+    }
+
+    /// Whether unbound symbols evaluate to themselves instead of raising
+    /// an error, as they did in older versions of risp.
+    pub fn lenient_symbols(&self) -> bool {
+        self.lenient_symbols.get()
+    }
+
+    /// Turn the old self-evaluating symbols on or off, for this scope and
+    /// every scope nested in it or sharing its root.
+    pub fn set_lenient_symbols(&self, lenient: bool) {
+        self.lenient_symbols.set(lenient);
+    }
+
+    /// Bind a builtin that is called with its arguments already evaluated.
+    /// The binding is an ordinary value, so it can be passed around.
     pub fn define_procedure(&mut self, symbol: &str, proc: RispFunc) {
-        self.data.insert(symbol.to_string(), RispExp::Symbol(symbol.to_string()));
-        self.funcs.insert(symbol.to_string(), proc);
+        self.define_closure(symbol, proc);
+    }
+
+    /// Like `define_procedure`, but for closures that capture their own
+    /// state.
+    pub fn define_closure<F>(&mut self, symbol: &str, func: F)
+    where
+        F: Fn(&[RispExp], &mut RispEnv) -> Result<RispExp, RispErr> + 'static,
+    {
+        let proc = RispExp::Proc(RispProc { name: symbol.to_string(), func: Rc::new(func) });
+        self.data.borrow_mut().insert(symbol.to_string(), proc);
+        self.help.borrow_mut().remove(symbol);
+    }
+
+    /// Register a Rust function taking and returning `FromRisp`/`IntoRisp`
+    /// types. Arity and type errors follow from its signature, e.g.
+    /// `env.register_fn("hypot", |a: f64, b: f64| a.hypot(b))`.
+    pub fn register_fn<Args, F>(&mut self, symbol: &str, func: F)
+    where
+        F: RispFn<Args> + 'static,
+    {
+        let name = symbol.to_string();
+        self.define_closure(symbol, move |args, _env| func.call(&name, args));
+    }
+
+    /// Bind a builtin that receives its arguments unevaluated, like `if`.
+    /// Special forms aren't values and can only be used in operator position.
+    pub fn define_special_form(&mut self, symbol: &str, form: RispFunc) {
+        self.funcs.borrow_mut().insert(symbol.to_string(), form);
+        self.help.borrow_mut().remove(symbol);
+    }
+
+    /// Describe the builtin just bound to `symbol` for `:help`, e.g.
+    /// `env.set_help("hypot", "(hypot a b) length of the hypotenuse")`.
+    /// Rebinding `symbol` forgets the description.
+    pub fn set_help(&self, symbol: &str, help: &str) {
+        self.help.borrow_mut().insert(symbol.to_string(), help.to_string());
+    }
+
+    /// The description of whatever `symbol` is bound to, from the scope
+    /// that binds it.
+    pub fn help(&self, symbol: &str) -> Option<String> {
+        if self.data.borrow().contains_key(symbol) || self.funcs.borrow().contains_key(symbol) {
+            self.help.borrow().get(symbol).cloned()
+        } else if let Some(outer) = &self.outer {
+            outer.help(symbol)
+        } else {
+            None
+        }
+    }
+
+    /// Remove every binding from this scope.
+    ///
+    /// A lambda holds a handle to the scope it was defined in, so a scope
+    /// that binds a lambda is part of an `Rc` cycle and is never freed on its
+    /// own. Clear a global scope once you're done with it to release it, and
+    /// with it anything captured by closures registered on it.
+    pub fn clear(&self) {
+        // Take the maps out first, dropping values may run arbitrary code
+        let data = std::mem::take(&mut *self.data.borrow_mut());
+        let funcs = std::mem::take(&mut *self.funcs.borrow_mut());
+        self.help.borrow_mut().clear();
+        drop(data);
+        drop(funcs);
     }
 
     pub fn define_variable(&mut self, symbol: &str, var: &RispExp) {
-        self.data.insert(symbol.to_string(), var.clone());
+        self.data.borrow_mut().insert(symbol.to_string(), var.clone());
+        self.help.borrow_mut().remove(symbol);
+    }
+
+    /// Rebind `symbol` in the nearest scope that already defines it. Returns
+    /// `false` if no scope in the chain binds it.
+    pub fn set(&self, symbol: &str, var: &RispExp) -> bool {
+        if let Some(slot) = self.data.borrow_mut().get_mut(symbol) {
+            *slot = var.clone();
+            self.help.borrow_mut().remove(symbol);
+            return true;
+        }
+        if let Some(outer) = &self.outer {
+            outer.set(symbol, var)
+        } else {
+            false
+        }
     }
 
     pub fn get(&self, symbol: &str) -> Option<RispExp> {
-        if let Some(s) = self.data.get(symbol) {
+        if let Some(s) = self.data.borrow().get(symbol) {
             Some(s.clone())
         } else if let Some(outer) = &self.outer {
             outer.get(symbol)
@@ -48,300 +175,429 @@ impl<'a> RispEnv<'a> {
         }
     }
 
-    pub fn get_function(&self, symbol: &str) -> Option<RispFunc> {
-        if let Some(s) = self.funcs.get(symbol) {
+    /// Every name bound in this scope or an outer one, variables and
+    /// procedures alike, sorted.
+    pub fn symbols(&self) -> Vec<String> {
+        let mut symbols = if let Some(outer) = &self.outer {
+            outer.symbols()
+        } else {
+            vec![]
+        };
+        symbols.extend(self.data.borrow().keys().cloned());
+        symbols.extend(self.funcs.borrow().keys().cloned());
+        symbols.sort();
+        symbols.dedup();
+        symbols
+    }
+
+    /// Bound names spelled like `symbol`, closest first, for "did you mean"
+    /// hints.
+    pub fn suggest(&self, symbol: &str) -> Vec<String> {
+        // Allow about one typo per three chars, but never a complete rewrite
+        let len = symbol.chars().count();
+        let max_distance = len.div_ceil(3).min(3);
+        let mut close: Vec<_> = self
+            .symbols()
+            .into_iter()
+            .map(|s| (edit_distance(symbol, &s), s))
+            .filter(|(d, _)| *d <= max_distance && *d < len)
+            .collect();
+        close.sort();
+        close.into_iter().take(3).map(|(_, s)| s).collect()
+    }
+
+    pub fn get_special_form(&self, symbol: &str) -> Option<RispFunc> {
+        if let Some(s) = self.funcs.borrow().get(symbol) {
             Some(*s)
         } else if let Some(outer) = &self.outer {
-            outer.get_function(symbol)
+            outer.get_special_form(symbol)
         } else {
             None
         }
     }
 }
 
-impl<'a> Default for RispEnv<'a> {
+/// Levenshtein distance between `a` and `b`, in chars.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut row = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitute = prev[j] + usize::from(ca != *cb);
+            row.push(substitute.min(prev[j + 1] + 1).min(row[j] + 1));
+        }
+        prev = row;
+    }
+    prev[b.len()]
+}
+
+impl Default for RispEnv {
     fn default() -> Self {
         Self::new()
     }
 }
 
-pub fn risp_if(args: &[RispExp], env: &mut RispEnv) -> Result<RispExp, RispErr> {
-    let (predicate, alternatives) = args.split_first().expect("`if` requires at least 3 arguments");
-    let predicate = eval(predicate.clone(), env).expect("failed to evaluate predicate");
-    match predicate {
-        RispExp::Bool(truth) => {
-            if truth {
-                // true
-                eval(alternatives[0].clone(), env)
-            } else {
-                // false
-                eval(alternatives[1].clone(), env)
-            }
-
-        },
-        _ => Err(RispErr::Reason(format!("{:?} does not evaluate to a boolean", predicate))),
+impl fmt::Debug for RispEnv {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Only list the names, values may be closures that refer back to
+        // this very scope.
+        let mut symbols: Vec<_> = self.data.borrow().keys().cloned().collect();
+        symbols.sort();
+        f.debug_struct("RispEnv")
+            .field("symbols", &symbols)
+            .field("outer", &self.outer)
+            .finish()
     }
 }
 
-pub fn risp_let(args: &[RispExp], env: &mut RispEnv) -> Result<RispExp, RispErr> {
-    let (symbol, expr) = args.split_first().expect("`let` requires at least 3 arguments");
-    // The fact that we don't eval(symbol) means the first argument has to be
-    // the symbol alone, no fanciness with lists allowed.
-    match symbol {
-        RispExp::Symbol(s) => {
-            // NOTE: we're tossing out expr[1..] here, intentionally.
-            let expr = eval(expr[0].clone(), env)?;
-            env.data.insert(s.clone(), expr.clone());
-            Ok(expr)
-        },
-        _ => Err(RispErr::Reason(format!("{:?} does not evaluate to a symbol", symbol))),
+/// Two environments are equal when they are handles to the same scope.
+impl PartialEq for RispEnv {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.data, &other.data)
     }
 }
 
-pub fn risp_lambda(args: &[RispExp], _env: &mut RispEnv) -> Result<RispExp, RispErr> {
-    let (params, func) = args.split_first().expect("`fn` requires 2 arguments");
+/// Evaluate `exp` as a condition, which must be a boolean.
+fn eval_condition(exp: &RispExp, env: &mut RispEnv) -> Result<bool, RispErr> {
+    match eval_ref(exp, env)? {
+        RispExp::Bool(truth) => Ok(truth),
+        other => Err(RispErr::type_error("a boolean", &other)),
+    }
+}
 
-    if func.len() > 1 {
-        return Err(RispErr::Reason("`fn` definition expected to only have 2 arguments".to_string()));
+/// Evaluate each expression in `body`, returning the last value or the
+/// empty list if there are none.
+fn eval_body(body: &[RispExp], env: &mut RispEnv) -> Result<RispExp, RispErr> {
+    let mut result = RispExp::List(vec![]);
+    for exp in body {
+        result = eval_ref(exp, env)?;
     }
+    Ok(result)
+}
 
-    Ok(RispExp::Lambda((Box::new(params.clone()), Box::new(func[0].clone()))))
+/// `(if pred then [else])`, a missing `else` gives the empty list.
+pub fn risp_if(args: &[RispExp], env: &mut RispEnv) -> Result<RispExp, RispErr> {
+    Arity::Between(2, 3).check("if", args)?;
+    if eval_condition(&args[0], env)? {
+        eval_ref(&args[1], env)
+    } else {
+        eval_body(&args[2..], env)
+    }
 }
 
-pub fn risp_add(args: &[RispExp], env: &mut RispEnv) -> Result<RispExp, RispErr> {
-    let mut total = 0.0;
-    for arg in args {
-        if let Ok(n) = eval_to_number(arg, env) {
-            total += n;
-        } else {
-            return Err(RispErr::Reason(format!("{:?} not a number", arg)));
+/// `(cond (pred exp...)... [(else exp...)])`, the body of the first clause
+/// whose predicate is true. A clause without a body gives the predicate's
+/// value.
+pub fn risp_cond(args: &[RispExp], env: &mut RispEnv) -> Result<RispExp, RispErr> {
+    for (i, clause) in args.iter().enumerate() {
+        let (test, body) = match clause.as_list()?.split_first() {
+            Some(split) => split,
+            None => return Err(RispErr::type_error("a cond clause", clause)),
         };
+        if test == &RispExp::Symbol("else".to_string()) {
+            if i + 1 != args.len() {
+                return Err(RispErr::Reason("`else` must be the last cond clause".to_string()));
+            }
+            return eval_body(body, env);
+        }
+        if eval_condition(test, env)? {
+            if body.is_empty() {
+                return Ok(RispExp::Bool(true));
+            }
+            return eval_body(body, env);
+        }
     }
-    Ok(RispExp::Number(total))
+    Ok(RispExp::List(vec![]))
 }
 
-pub fn risp_subtract(args: &[RispExp], env: &mut RispEnv) -> Result<RispExp, RispErr> {
-    //println!("- args: {:?}", args);
-    let (first, rest_nums) = args.split_first().expect("`-` requires at least 2 arguments");
-    let num1 = if let Ok(n) = eval_to_number(first, env) {
-        n
+/// `(when pred exp...)`, evaluate the body if `pred` is true.
+pub fn risp_when(args: &[RispExp], env: &mut RispEnv) -> Result<RispExp, RispErr> {
+    Arity::AtLeast(2).check("when", args)?;
+    if eval_condition(&args[0], env)? {
+        eval_body(&args[1..], env)
     } else {
-        return Err(RispErr::Reason(format!("{:?} not a number", first)));
-    };
+        Ok(RispExp::List(vec![]))
+    }
+}
 
-    let mut sum_right = 0.0;
-    for num in rest_nums {
-        let num = if let Ok(n) = eval_to_number(num, env) {
-            n
-        } else {
-            return Err(RispErr::Reason(format!("{:?} not a number", first)));
-        };
+/// `(unless pred exp...)`, evaluate the body if `pred` is false.
+pub fn risp_unless(args: &[RispExp], env: &mut RispEnv) -> Result<RispExp, RispErr> {
+    Arity::AtLeast(2).check("unless", args)?;
+    if eval_condition(&args[0], env)? {
+        Ok(RispExp::List(vec![]))
+    } else {
+        eval_body(&args[1..], env)
+    }
+}
 
-        sum_right += num;
+/// `(case key ((datum...) exp...)... [(else exp...)])`, the body of the
+/// first clause listing a datum equal to `key`. Datums aren't evaluated.
+pub fn risp_case(args: &[RispExp], env: &mut RispEnv) -> Result<RispExp, RispErr> {
+    Arity::AtLeast(1).check("case", args)?;
+    let key = eval_ref(&args[0], env)?;
+    let clauses = &args[1..];
+    for (i, clause) in clauses.iter().enumerate() {
+        let (datums, body) = match clause.as_list()?.split_first() {
+            Some(split) => split,
+            None => return Err(RispErr::type_error("a case clause", clause)),
+        };
+        let matched = match datums {
+            RispExp::Symbol(s) if s == "else" => {
+                if i + 1 != clauses.len() {
+                    return Err(RispErr::Reason("`else` must be the last case clause".to_string()));
+                }
+                true
+            },
+            RispExp::List(datums) => datums.contains(&key),
+            datum => datum == &key,
+        };
+        if matched {
+            return eval_body(body, env);
+        }
     }
+    Ok(RispExp::List(vec![]))
+}
 
-    Ok(RispExp::Number(num1 - sum_right))
+/// True if every argument is, stopping at the first false one.
+pub fn risp_and(args: &[RispExp], env: &mut RispEnv) -> Result<RispExp, RispErr> {
+    for arg in args {
+        if !eval_condition(arg, env)? {
+            return Ok(RispExp::Bool(false));
+        }
+    }
+    Ok(RispExp::Bool(true))
 }
 
-pub fn risp_multiply(args: &[RispExp], env: &mut RispEnv) -> Result<RispExp, RispErr> {
-    let mut total = 1.0;
+/// True if any argument is, stopping at the first true one.
+pub fn risp_or(args: &[RispExp], env: &mut RispEnv) -> Result<RispExp, RispErr> {
     for arg in args {
-        if let Ok(n) = eval_to_number(arg, env) {
-            total *= n;
-        } else {
-            return Err(RispErr::Reason(format!("{:?} not a number", arg)));
-        };
+        if eval_condition(arg, env)? {
+            return Ok(RispExp::Bool(true));
+        }
     }
-    Ok(RispExp::Number(total))
+    Ok(RispExp::Bool(false))
 }
 
-pub fn risp_divide(args: &[RispExp], env: &mut RispEnv) -> Result<RispExp, RispErr> {
-    let (first, rest_nums) = args.split_first().expect("`/` requires at least 2 arguments");
-    if rest_nums.len() > 1 {
-        return Err(RispErr::Reason("`/` takes exactly 2 arguments".to_string()));
+pub fn risp_not(args: &[RispExp], _env: &mut RispEnv) -> Result<RispExp, RispErr> {
+    Arity::Exactly(1).check("not", args)?;
+    match &args[0] {
+        RispExp::Bool(b) => Ok(RispExp::Bool(!b)),
+        other => Err(RispErr::type_error("a boolean", other)),
     }
+}
 
-    let numerator = if let Ok(n) = eval_to_number(first, env) {
-        n
-    } else {
-        return Err(RispErr::Reason(format!("{:?} not a number", first)));
-    };
+/// Evaluate each argument in turn, returning the value of the last one.
+/// `name` is what the form was called as, for errors.
+fn sequence(name: &str, args: &[RispExp], env: &mut RispEnv) -> Result<RispExp, RispErr> {
+    Arity::AtLeast(1).check(name, args)?;
+    eval_body(args, env)
+}
 
-    let denominator = if let Ok(n) = eval_to_number(&rest_nums[0], env) {
-        n
-    } else {
-        return Err(RispErr::Reason(format!("{:?} not a number", first)));
-    };
+pub fn risp_begin(args: &[RispExp], env: &mut RispEnv) -> Result<RispExp, RispErr> {
+    sequence("begin", args, env)
+}
 
-    Ok(RispExp::Number(numerator / denominator))
+/// Same as `begin`.
+pub fn risp_do(args: &[RispExp], env: &mut RispEnv) -> Result<RispExp, RispErr> {
+    sequence("do", args, env)
 }
 
-pub fn risp_cosine(args: &[RispExp], env: &mut RispEnv) -> Result<RispExp, RispErr> {
-    if args.len() > 1 {
-        return Err(RispErr::Reason("`cos` takes exactly 1 argument".to_string()));
+pub fn risp_let(args: &[RispExp], env: &mut RispEnv) -> Result<RispExp, RispErr> {
+    Arity::AtLeast(2).check("let", args)?;
+    let (symbol, expr) = (&args[0], &args[1..]);
+    // The fact that we don't eval(symbol) means the first argument has to be
+    // the symbol alone, no fanciness with lists allowed.
+    match symbol {
+        RispExp::Symbol(s) => {
+            // NOTE: we're tossing out expr[1..] here, intentionally.
+            let expr = eval_ref(&expr[0], env)?;
+            env.define_variable(s, &expr);
+            Ok(expr)
+        },
+        _ => Err(RispErr::type_error("a symbol", symbol)),
     }
-    let num = if let Ok(n) = eval_to_number(&args[0], env) {
-        n
-    } else {
-        return Err(RispErr::Reason(format!("{:?} not a number", args[0])));
-    };
-    Ok(RispExp::Number(num.cos()))
 }
 
-pub fn risp_sine(args: &[RispExp], env: &mut RispEnv) -> Result<RispExp, RispErr> {
-    if args.len() > 1 {
-        return Err(RispErr::Reason("`sin` takes exactly 1 argument".to_string()));
+pub fn risp_set(args: &[RispExp], env: &mut RispEnv) -> Result<RispExp, RispErr> {
+    Arity::Exactly(2).check("set!", args)?;
+
+    match &args[0] {
+        RispExp::Symbol(s) => {
+            let expr = eval_ref(&args[1], env)?;
+            if env.set(s, &expr) {
+                Ok(expr)
+            } else {
+                Err(RispErr::unbound(s, env))
+            }
+        },
+        other => Err(RispErr::type_error("a symbol", other)),
     }
-    let num = if let Ok(n) = eval_to_number(&args[0], env) {
-        n
-    } else {
-        return Err(RispErr::Reason(format!("{:?} not a number", args[0])));
-    };
-    Ok(RispExp::Number(num.sin()))
 }
 
-pub fn risp_tangent(args: &[RispExp], env: &mut RispEnv) -> Result<RispExp, RispErr> {
-    if args.len() > 1 {
-        return Err(RispErr::Reason("`tan` takes exactly 1 argument".to_string()));
-    }
-    let num = if let Ok(n) = eval_to_number(&args[0], env) {
-        n
+pub fn risp_lambda(args: &[RispExp], env: &mut RispEnv) -> Result<RispExp, RispErr> {
+    Arity::Exactly(2).check("fn", args)?;
+
+    let params = if let RispExp::List(pars) = &args[0] {
+        pars
     } else {
-        return Err(RispErr::Reason(format!("{:?} not a number", args[0])));
+        return Err(RispErr::type_error("a parameter list", &args[0]));
     };
-    Ok(RispExp::Number(num.tan()))
-}
 
-pub fn risp_acos(args: &[RispExp], env: &mut RispEnv) -> Result<RispExp, RispErr> {
-    if args.len() > 1 {
-        return Err(RispErr::Reason("`acos` takes exactly 1 argument".to_string()));
+    let mut names = vec![];
+    for param in params {
+        if let RispExp::Symbol(s) = param {
+            names.push(s.clone());
+        } else {
+            return Err(RispErr::type_error("a symbol", param));
+        }
     }
-    let num = if let Ok(n) = eval_to_number(&args[0], env) {
-        n
-    } else {
-        return Err(RispErr::Reason(format!("{:?} not a number", args[0])));
-    };
-    Ok(RispExp::Number(num.acos()))
+
+    Ok(RispExp::Lambda(RispLambda {
+        params: names,
+        body: Box::new(args[1].clone()),
+        closure: env.clone(),
+    }))
 }
 
-pub fn risp_asin(args: &[RispExp], env: &mut RispEnv) -> Result<RispExp, RispErr> {
-    if args.len() > 1 {
-        return Err(RispErr::Reason("`asin` takes exactly 1 argument".to_string()));
-    }
-    let num = if let Ok(n) = eval_to_number(&args[0], env) {
-        n
-    } else {
-        return Err(RispErr::Reason(format!("{:?} not a number", args[0])));
-    };
-    Ok(RispExp::Number(num.asin()))
+/// Return the argument as data, without evaluating it.
+pub fn risp_quote(args: &[RispExp], _env: &mut RispEnv) -> Result<RispExp, RispErr> {
+    Arity::Exactly(1).check("quote", args)?;
+    Ok(args[0].clone())
 }
 
-pub fn risp_atan(args: &[RispExp], env: &mut RispEnv) -> Result<RispExp, RispErr> {
-    if args.len() > 1 {
-        return Err(RispErr::Reason("`atan` takes exactly 1 argument".to_string()));
-    }
-    let num = if let Ok(n) = eval_to_number(&args[0], env) {
-        n
-    } else {
-        return Err(RispErr::Reason(format!("{:?} not a number", args[0])));
-    };
-    Ok(RispExp::Number(num.atan()))
+/// Like `quote`, but `(unquote x)` parts are evaluated and the lists from
+/// `(unquote-splicing x)` parts are spliced into the surrounding list.
+pub fn risp_quasiquote(args: &[RispExp], env: &mut RispEnv) -> Result<RispExp, RispErr> {
+    Arity::Exactly(1).check("quasiquote", args)?;
+    quasi(&args[0], 1, env)
 }
 
-pub fn risp_log(args: &[RispExp], env: &mut RispEnv) -> Result<RispExp, RispErr> {
-    if args.len() > 1 {
-        return Err(RispErr::Reason("`log` takes exactly 1 argument".to_string()));
+/// If `exp` is `(name x)`, return `x`.
+fn unwrap_form<'e>(exp: &'e RispExp, name: &str) -> Option<&'e RispExp> {
+    match exp {
+        RispExp::List(v) if v.len() == 2 && v[0] == RispExp::Symbol(name.to_string()) => Some(&v[1]),
+        _ => None,
     }
-    let num = if let Ok(n) = eval_to_number(&args[0], env) {
-        n
-    } else {
-        return Err(RispErr::Reason(format!("{:?} not a number", args[0])));
-    };
-    Ok(RispExp::Number(num.ln()))
 }
 
-pub fn risp_log2(args: &[RispExp], env: &mut RispEnv) -> Result<RispExp, RispErr> {
-    if args.len() > 1 {
-        return Err(RispErr::Reason("`log2` takes exactly 1 argument".to_string()));
+/// Expand a quasiquoted template. `depth` counts the enclosing quasiquotes,
+/// only unquotes at depth 1 are evaluated, deeper ones stay as data.
+fn quasi(exp: &RispExp, depth: usize, env: &mut RispEnv) -> Result<RispExp, RispErr> {
+    if let Some(inner) = unwrap_form(exp, "unquote") {
+        if depth == 1 {
+            return eval_ref(inner, env);
+        }
+        let inner = quasi(inner, depth - 1, env)?;
+        return Ok(RispExp::List(vec![RispExp::Symbol("unquote".to_string()), inner]));
     }
-    let num = if let Ok(n) = eval_to_number(&args[0], env) {
-        n
-    } else {
-        return Err(RispErr::Reason(format!("{:?} not a number", args[0])));
+    // Splices inside a list are handled along with the other items below
+    if let Some(inner) = unwrap_form(exp, "unquote-splicing") {
+        if depth == 1 {
+            return Err(RispErr::Reason("`unquote-splicing` must appear inside a list".to_string()));
+        }
+        let inner = quasi(inner, depth - 1, env)?;
+        return Ok(RispExp::List(vec![RispExp::Symbol("unquote-splicing".to_string()), inner]));
+    }
+    if let Some(inner) = unwrap_form(exp, "quasiquote") {
+        let inner = quasi(inner, depth + 1, env)?;
+        return Ok(RispExp::List(vec![RispExp::Symbol("quasiquote".to_string()), inner]));
+    }
+
+    let items = match exp {
+        RispExp::List(v) => v,
+        _ => return Ok(exp.clone()),
     };
-    Ok(RispExp::Number(num.log2()))
+    let mut out = vec![];
+    for item in items {
+        match unwrap_form(item, "unquote-splicing") {
+            Some(inner) if depth == 1 => match eval_ref(inner, env)? {
+                RispExp::List(v) => out.extend(v),
+                other => return Err(RispErr::type_error("a list", &other)),
+            },
+            Some(inner) => {
+                let inner = quasi(inner, depth - 1, env)?;
+                out.push(RispExp::List(vec![RispExp::Symbol("unquote-splicing".to_string()), inner]));
+            },
+            None => out.push(quasi(item, depth, env)?),
+        }
+    }
+    Ok(RispExp::List(out))
 }
 
-pub fn risp_log10(args: &[RispExp], env: &mut RispEnv) -> Result<RispExp, RispErr> {
-    if args.len() > 1 {
-        return Err(RispErr::Reason("`log10` takes exactly 1 argument".to_string()));
-    }
-    let num = if let Ok(n) = eval_to_number(&args[0], env) {
-        n
-    } else {
-        return Err(RispErr::Reason(format!("{:?} not a number", args[0])));
-    };
-    Ok(RispExp::Number(num.log10()))
+/// `unquote` and `unquote-splicing` only mean something inside a
+/// quasiquote, which handles them itself.
+pub fn risp_unquote(_args: &[RispExp], _env: &mut RispEnv) -> Result<RispExp, RispErr> {
+    Err(RispErr::Reason("`unquote` used outside of `quasiquote`".to_string()))
 }
 
-pub fn risp_sqrt(args: &[RispExp], env: &mut RispEnv) -> Result<RispExp, RispErr> {
-    if args.len() > 1 {
-        return Err(RispErr::Reason("`sqrt` takes exactly 1 argument".to_string()));
+pub fn risp_unquote_splicing(_args: &[RispExp], _env: &mut RispEnv) -> Result<RispExp, RispErr> {
+    Err(RispErr::Reason("`unquote-splicing` used outside of `quasiquote`".to_string()))
+}
+
+pub fn risp_error(args: &[RispExp], _env: &mut RispEnv) -> Result<RispExp, RispErr> {
+    Arity::Exactly(1).check("error", args)?;
+    Err(RispErr::User(args[0].clone()))
+}
+
+/// Print the arguments separated by spaces, followed by a newline. Strings
+/// are printed without quotes.
+pub fn risp_println(args: &[RispExp], _env: &mut RispEnv) -> Result<RispExp, RispErr> {
+    let mut parts = vec![];
+    for arg in args {
+        match arg {
+            RispExp::Str(s) => parts.push(s.clone()),
+            other => parts.push(other.to_string()),
+        }
     }
-    let num = if let Ok(n) = eval_to_number(&args[0], env) {
-        n
-    } else {
-        return Err(RispErr::Reason(format!("{:?} not a number", args[0])));
-    };
-    Ok(RispExp::Number(num.sqrt()))
+    println!("{}", parts.join(" "));
+    Ok(RispExp::List(vec![]))
 }
 
-pub fn risp_exp(args: &[RispExp], env: &mut RispEnv) -> Result<RispExp, RispErr> {
-    if args.len() > 1 {
-        return Err(RispErr::Reason("`exp` takes exactly 1 argument".to_string()));
+pub fn risp_add(args: &[RispExp], _env: &mut RispEnv) -> Result<RispExp, RispErr> {
+    let mut total = 0.0;
+    for arg in args {
+        total += arg.as_number()?;
     }
-    let num = if let Ok(n) = eval_to_number(&args[0], env) {
-        n
-    } else {
-        return Err(RispErr::Reason(format!("{:?} not a number", args[0])));
-    };
-    Ok(RispExp::Number(num.exp()))
+    Ok(RispExp::Number(total))
 }
 
-pub fn risp_abs(args: &[RispExp], env: &mut RispEnv) -> Result<RispExp, RispErr> {
-    if args.len() > 1 {
-        return Err(RispErr::Reason("`abs` takes exactly 1 argument".to_string()));
+pub fn risp_subtract(args: &[RispExp], _env: &mut RispEnv) -> Result<RispExp, RispErr> {
+    Arity::AtLeast(1).check("-", args)?;
+    let (first, rest_nums) = (&args[0], &args[1..]);
+    let num1 = first.as_number()?;
+
+    let mut sum_right = 0.0;
+    for num in rest_nums {
+        sum_right += num.as_number()?;
     }
-    let num = if let Ok(n) = eval_to_number(&args[0], env) {
-        n
-    } else {
-        return Err(RispErr::Reason(format!("{:?} not a number", args[0])));
-    };
-    Ok(RispExp::Number(num.abs()))
+
+    Ok(RispExp::Number(num1 - sum_right))
 }
 
-pub fn risp_pow(args: &[RispExp], env: &mut RispEnv) -> Result<RispExp, RispErr> {
-    if args.len() != 2 {
-        return Err(RispErr::Reason("`pow` takes exactly 2 arguments".to_string()));
+pub fn risp_multiply(args: &[RispExp], _env: &mut RispEnv) -> Result<RispExp, RispErr> {
+    let mut total = 1.0;
+    for arg in args {
+        total *= arg.as_number()?;
     }
+    Ok(RispExp::Number(total))
+}
 
-    let base = if let Ok(n) = eval_to_number(&args[0], env) {
-        n
-    } else {
-        return Err(RispErr::Reason(format!("{:?} not a number", args[0])));
-    };
+pub fn risp_divide(args: &[RispExp], _env: &mut RispEnv) -> Result<RispExp, RispErr> {
+    Arity::Exactly(2).check("/", args)?;
 
-    let power = if let Ok(n) = eval_to_number(&args[1], env) {
-        n
-    } else {
-        return Err(RispErr::Reason(format!("{:?} not a number", args[1])));
-    };
+    let numerator = args[0].as_number()?;
+    let denominator = args[1].as_number()?;
+    if denominator == 0.0 {
+        return Err(RispErr::DivideByZero);
+    }
 
-    Ok(RispExp::Number(base.powf(power)))
+    Ok(RispExp::Number(numerator / denominator))
 }
 
 pub fn risp_eq(args: &[RispExp], _env: &mut RispEnv) -> Result<RispExp, RispErr> {
-    let (left, others) = args.split_first().expect("`=` requires at least 2 arguments");
+    Arity::AtLeast(2).check("=", args)?;
+    let (left, others) = (&args[0], &args[1..]);
 
     for other in others {
         if left != other {
@@ -353,7 +609,8 @@ pub fn risp_eq(args: &[RispExp], _env: &mut RispEnv) -> Result<RispExp, RispErr>
 }
 
 pub fn risp_neq(args: &[RispExp], _env: &mut RispEnv) -> Result<RispExp, RispErr> {
-    let (left, others) = args.split_first().expect("`!=` requires at least 2 arguments");
+    Arity::AtLeast(2).check("!=", args)?;
+    let (left, others) = (&args[0], &args[1..]);
 
     for other in others {
         if left != other {
@@ -364,12 +621,13 @@ pub fn risp_neq(args: &[RispExp], _env: &mut RispEnv) -> Result<RispExp, RispErr
     Ok(RispExp::Bool(false))
 }
 
-pub fn risp_gt(args: &[RispExp], env: &mut RispEnv) -> Result<RispExp, RispErr> {
-    let (left, others) = args.split_first().expect("`>` requires at least 2 arguments");
+pub fn risp_gt(args: &[RispExp], _env: &mut RispEnv) -> Result<RispExp, RispErr> {
+    Arity::AtLeast(2).check(">", args)?;
+    let (left, others) = (&args[0], &args[1..]);
 
-    let left = eval_to_number(left, env)?;
+    let left = left.as_number()?;
     for other in others {
-        let other = eval_to_number(other, env)?;
+        let other = other.as_number()?;
         if left <= other {
             return Ok(RispExp::Bool(false));
         }
@@ -378,12 +636,13 @@ pub fn risp_gt(args: &[RispExp], env: &mut RispEnv) -> Result<RispExp, RispErr>
     Ok(RispExp::Bool(true))
 }
 
-pub fn risp_gte(args: &[RispExp], env: &mut RispEnv) -> Result<RispExp, RispErr> {
-    let (left, others) = args.split_first().expect("`>=` requires at least 2 arguments");
+pub fn risp_gte(args: &[RispExp], _env: &mut RispEnv) -> Result<RispExp, RispErr> {
+    Arity::AtLeast(2).check(">=", args)?;
+    let (left, others) = (&args[0], &args[1..]);
 
-    let left = eval_to_number(left, env)?;
+    let left = left.as_number()?;
     for other in others {
-        let other = eval_to_number(other, env)?;
+        let other = other.as_number()?;
         if left < other {
             return Ok(RispExp::Bool(false));
         }
@@ -393,9 +652,12 @@ pub fn risp_gte(args: &[RispExp], env: &mut RispEnv) -> Result<RispExp, RispErr>
 }
 
 pub fn risp_lt(args: &[RispExp], _env: &mut RispEnv) -> Result<RispExp, RispErr> {
-    let (left, others) = args.split_first().expect("`<` requires at least 2 arguments");
+    Arity::AtLeast(2).check("<", args)?;
+    let (left, others) = (&args[0], &args[1..]);
 
+    let left = left.as_number()?;
     for other in others {
+        let other = other.as_number()?;
         if left >= other {
             return Ok(RispExp::Bool(false));
         }
@@ -405,9 +667,12 @@ pub fn risp_lt(args: &[RispExp], _env: &mut RispEnv) -> Result<RispExp, RispErr>
 }
 
 pub fn risp_lte(args: &[RispExp], _env: &mut RispEnv) -> Result<RispExp, RispErr> {
-    let (left, others) = args.split_first().expect("`<=` requires at least 2 arguments");
+    Arity::AtLeast(2).check("<=", args)?;
+    let (left, others) = (&args[0], &args[1..]);
 
+    let left = left.as_number()?;
     for other in others {
+        let other = other.as_number()?;
         if left > other {
             return Ok(RispExp::Bool(false));
         }
@@ -416,96 +681,513 @@ pub fn risp_lte(args: &[RispExp], _env: &mut RispEnv) -> Result<RispExp, RispErr
     Ok(RispExp::Bool(true))
 }
 
-pub fn standard_env<'a>() -> RispEnv<'a> {
-    let mut env = RispEnv::default();
-    env.define_variable("pi", &RispExp::Number(f64::consts::PI));
-    env.define_procedure("if", risp_if as RispFunc);
-    env.define_procedure("let", risp_let as RispFunc);
-    env.define_procedure("fn", risp_lambda as RispFunc);
-    env.define_procedure("+", risp_add as RispFunc);
-    env.define_procedure("-", risp_subtract as RispFunc);
-    env.define_procedure("*", risp_multiply as RispFunc);
-    env.define_procedure("/", risp_divide as RispFunc);
-    env.define_procedure("cos", risp_cosine as RispFunc);
-    env.define_procedure("sin", risp_sine as RispFunc);
-    env.define_procedure("tan", risp_tangent as RispFunc);
-    env.define_procedure("acos", risp_acos as RispFunc);
-    env.define_procedure("asin", risp_asin as RispFunc);
-    env.define_procedure("atan", risp_atan as RispFunc);
-    env.define_procedure("log", risp_log as RispFunc);
-    env.define_procedure("log2", risp_log2 as RispFunc);
-    env.define_procedure("log10", risp_log10 as RispFunc);
-    env.define_procedure("sqrt", risp_sqrt as RispFunc);
-    env.define_procedure("exp", risp_exp as RispFunc);
-    env.define_procedure("abs", risp_abs as RispFunc);
-    env.define_procedure("pow", risp_pow as RispFunc);
-    env.define_procedure("=", risp_eq as RispFunc);
-    env.define_procedure("!=", risp_neq as RispFunc);
-    env.define_procedure(">", risp_gt as RispFunc);
-    env.define_procedure(">=", risp_gte as RispFunc);
-    env.define_procedure("<", risp_lt as RispFunc);
-    env.define_procedure("<=", risp_lte as RispFunc);
-    env
+pub fn risp_string_append(args: &[RispExp], _env: &mut RispEnv) -> Result<RispExp, RispErr> {
+    let mut out = String::new();
+    for arg in args {
+        out.push_str(arg.as_str()?);
+    }
+    Ok(RispExp::Str(out))
 }
 
-#[cfg(test)]
-mod tests {
-    use crate::*;
-    use std::f64;
-
-    #[test]
-    fn test_add() {
-        let expr = "(+ 10 5)";
-        let mut env = standard_env();
-        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
-        assert_eq!(output, RispExp::Number(15_f64));
+pub fn risp_string_length(args: &[RispExp], _env: &mut RispEnv) -> Result<RispExp, RispErr> {
+    Arity::Exactly(1).check("string-length", args)?;
+    let s = args[0].as_str()?;
+    Ok(RispExp::Number(s.chars().count() as f64))
+}
 
-        let expr = "(+ 10 5 3 1 -12)";
-        let mut env = standard_env();
-        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
-        assert_eq!(output, RispExp::Number(7_f64));
+/// `(substring s start [end])`, indices count chars and `end` is exclusive.
+pub fn risp_substring(args: &[RispExp], _env: &mut RispEnv) -> Result<RispExp, RispErr> {
+    Arity::Between(2, 3).check("substring", args)?;
+    let s = args[0].as_str()?;
+    let len = s.chars().count();
+    let start = args[1].as_number()?;
+    let end = if args.len() == 3 {
+        args[2].as_number()?
+    } else {
+        len as f64
+    };
 
-        let expr = "(+ 10 (+ 5 (+ 1 2)) 1 -12)";
-        let mut env = standard_env();
-        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
-        assert_eq!(output, RispExp::Number(7_f64));
+    if start < 0.0 || end < start || end > len as f64 || start.fract() != 0.0 || end.fract() != 0.0 {
+        return Err(RispErr::Reason(format!(
+            "`substring` range {}..{} is invalid for a string of length {}", start, end, len
+        )));
     }
 
-    #[test]
-    fn test_subtract() {
-        let expr = "(- 10 5)";
-        let mut env = standard_env();
-        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
-        assert_eq!(output, RispExp::Number(5_f64));
+    let out = s.chars().skip(start as usize).take((end - start) as usize).collect();
+    Ok(RispExp::Str(out))
+}
 
-        let expr = "(- 10 (- 8 3) 3 1 -12)";
-        let mut env = standard_env();
-        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
-        assert_eq!(output, RispExp::Number(13_f64));
-    }
+/// An empty separator splits on runs of whitespace.
+pub fn risp_string_split(args: &[RispExp], _env: &mut RispEnv) -> Result<RispExp, RispErr> {
+    Arity::Exactly(2).check("string-split", args)?;
+    let s = args[0].as_str()?;
+    let sep = args[1].as_str()?;
+    let parts = if sep.is_empty() {
+        s.split_whitespace().map(|p| RispExp::Str(p.to_string())).collect()
+    } else {
+        s.split(sep).map(|p| RispExp::Str(p.to_string())).collect()
+    };
+    Ok(RispExp::List(parts))
+}
 
-    #[test]
-    fn test_multiply() {
-        let expr = "(* 10 5)";
-        let mut env = standard_env();
-        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
-        assert_eq!(output, RispExp::Number(50_f64));
+pub fn risp_string_join(args: &[RispExp], _env: &mut RispEnv) -> Result<RispExp, RispErr> {
+    Arity::Exactly(2).check("string-join", args)?;
+    let parts = match &args[0] {
+        RispExp::List(v) => v,
+        other => return Err(RispErr::type_error("a list", other)),
+    };
+    let sep = args[1].as_str()?;
 
-        let expr = "(* 10 (- 8 3) 3 1)";
-        let mut env = standard_env();
-        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
-        assert_eq!(output, RispExp::Number(150_f64));
+    let mut strs = vec![];
+    for part in parts {
+        match part {
+            RispExp::Str(s) => strs.push(s.as_str()),
+            other => return Err(RispErr::type_error("a string", other)),
+        }
     }
+    Ok(RispExp::Str(strs.join(sep)))
+}
 
-    #[test]
-    fn test_divide() {
-        let expr = "(/ 10 5)";
-        let mut env = standard_env();
-        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
-        assert_eq!(output, RispExp::Number(2_f64));
+pub fn risp_string_upcase(args: &[RispExp], _env: &mut RispEnv) -> Result<RispExp, RispErr> {
+    Arity::Exactly(1).check("string-upcase", args)?;
+    Ok(RispExp::Str(args[0].as_str()?.to_uppercase()))
+}
 
-        let expr = "(/ 150 (- 8 3))";
-        let mut env = standard_env();
+pub fn risp_string_downcase(args: &[RispExp], _env: &mut RispEnv) -> Result<RispExp, RispErr> {
+    Arity::Exactly(1).check("string-downcase", args)?;
+    Ok(RispExp::Str(args[0].as_str()?.to_lowercase()))
+}
+
+pub fn risp_string_trim(args: &[RispExp], _env: &mut RispEnv) -> Result<RispExp, RispErr> {
+    Arity::Exactly(1).check("string-trim", args)?;
+    Ok(RispExp::Str(args[0].as_str()?.trim().to_string()))
+}
+
+pub fn risp_string_contains(args: &[RispExp], _env: &mut RispEnv) -> Result<RispExp, RispErr> {
+    Arity::Exactly(2).check("string-contains?", args)?;
+    let s = args[0].as_str()?;
+    let needle = args[1].as_str()?;
+    Ok(RispExp::Bool(s.contains(needle)))
+}
+
+pub fn risp_number_to_string(args: &[RispExp], _env: &mut RispEnv) -> Result<RispExp, RispErr> {
+    Arity::Exactly(1).check("number->string", args)?;
+    // Same formatting as printing the number
+    Ok(RispExp::Str(format_number(args[0].as_number()?)))
+}
+
+/// Returns `false` when the string isn't a number, like Scheme does.
+pub fn risp_string_to_number(args: &[RispExp], _env: &mut RispEnv) -> Result<RispExp, RispErr> {
+    Arity::Exactly(1).check("string->number", args)?;
+    match args[0].as_str()?.trim().parse() {
+        Ok(n) => Ok(RispExp::Number(n)),
+        Err(_) => Ok(RispExp::Bool(false)),
+    }
+}
+
+pub fn risp_list(args: &[RispExp], _env: &mut RispEnv) -> Result<RispExp, RispErr> {
+    Ok(RispExp::List(args.to_vec()))
+}
+
+/// `(cons x list)`, a new list with `x` in front of the elements of `list`.
+pub fn risp_cons(args: &[RispExp], _env: &mut RispEnv) -> Result<RispExp, RispErr> {
+    Arity::Exactly(2).check("cons", args)?;
+    let mut list = vec![args[0].clone()];
+    list.extend_from_slice(args[1].as_list()?);
+    Ok(RispExp::List(list))
+}
+
+/// The first element of a list, `name` is what it was called as, for
+/// errors.
+fn list_first(name: &str, args: &[RispExp]) -> Result<RispExp, RispErr> {
+    Arity::Exactly(1).check(name, args)?;
+    match args[0].as_list()?.first() {
+        Some(first) => Ok(first.clone()),
+        None => Err(RispErr::Reason("can't take the first element of an empty list".to_string())),
+    }
+}
+
+/// A list without its first element, `name` is what it was called as, for
+/// errors.
+fn list_rest(name: &str, args: &[RispExp]) -> Result<RispExp, RispErr> {
+    Arity::Exactly(1).check(name, args)?;
+    match args[0].as_list()?.split_first() {
+        Some((_, rest)) => Ok(RispExp::List(rest.to_vec())),
+        None => Err(RispErr::Reason("can't take the rest of an empty list".to_string())),
+    }
+}
+
+pub fn risp_car(args: &[RispExp], _env: &mut RispEnv) -> Result<RispExp, RispErr> {
+    list_first("car", args)
+}
+
+pub fn risp_first(args: &[RispExp], _env: &mut RispEnv) -> Result<RispExp, RispErr> {
+    list_first("first", args)
+}
+
+pub fn risp_cdr(args: &[RispExp], _env: &mut RispEnv) -> Result<RispExp, RispErr> {
+    list_rest("cdr", args)
+}
+
+pub fn risp_rest(args: &[RispExp], _env: &mut RispEnv) -> Result<RispExp, RispErr> {
+    list_rest("rest", args)
+}
+
+/// `(nth list i)`, the element at zero based index `i`.
+pub fn risp_nth(args: &[RispExp], _env: &mut RispEnv) -> Result<RispExp, RispErr> {
+    Arity::Exactly(2).check("nth", args)?;
+    let list = args[0].as_list()?;
+    let index = args[1].as_number()?;
+    if index < 0.0 || index.fract() != 0.0 || index as usize >= list.len() {
+        return Err(RispErr::Reason(format!(
+            "index {} is out of range for a list of length {}", index, list.len()
+        )));
+    }
+    Ok(list[index as usize].clone())
+}
+
+pub fn risp_append(args: &[RispExp], _env: &mut RispEnv) -> Result<RispExp, RispErr> {
+    let mut out = vec![];
+    for arg in args {
+        out.extend_from_slice(arg.as_list()?);
+    }
+    Ok(RispExp::List(out))
+}
+
+pub fn risp_length(args: &[RispExp], _env: &mut RispEnv) -> Result<RispExp, RispErr> {
+    Arity::Exactly(1).check("length", args)?;
+    Ok(RispExp::Number(args[0].as_list()?.len() as f64))
+}
+
+pub fn risp_reverse(args: &[RispExp], _env: &mut RispEnv) -> Result<RispExp, RispErr> {
+    Arity::Exactly(1).check("reverse", args)?;
+    Ok(RispExp::List(args[0].as_list()?.iter().rev().cloned().collect()))
+}
+
+/// True only for the empty list, any other value isn't empty.
+/// Whether the argument is the empty list, `name` is what it was called
+/// as, for errors.
+fn is_empty(name: &str, args: &[RispExp]) -> Result<RispExp, RispErr> {
+    Arity::Exactly(1).check(name, args)?;
+    Ok(RispExp::Bool(matches!(&args[0], RispExp::List(v) if v.is_empty())))
+}
+
+pub fn risp_is_empty(args: &[RispExp], _env: &mut RispEnv) -> Result<RispExp, RispErr> {
+    is_empty("empty?", args)
+}
+
+pub fn risp_is_null(args: &[RispExp], _env: &mut RispEnv) -> Result<RispExp, RispErr> {
+    is_empty("null?", args)
+}
+
+/// Transpose `lists` into rows of arguments, stopping at the shortest list.
+fn zip_lists(lists: &[RispExp]) -> Result<Vec<Vec<RispExp>>, RispErr> {
+    let lists: Vec<_> = lists.iter().map(|l| l.as_list()).collect::<Result<_, _>>()?;
+    let len = lists.iter().map(|l| l.len()).min().unwrap_or(0);
+    let mut rows = vec![];
+    for i in 0..len {
+        rows.push(lists.iter().map(|l| l[i].clone()).collect());
+    }
+    Ok(rows)
+}
+
+/// `(map f list...)`, call `f` with one element from each list at a time.
+pub fn risp_map(args: &[RispExp], env: &mut RispEnv) -> Result<RispExp, RispErr> {
+    Arity::AtLeast(2).check("map", args)?;
+    let mut out = vec![];
+    for row in zip_lists(&args[1..])? {
+        out.push(apply(&args[0], &row, env)?);
+    }
+    Ok(RispExp::List(out))
+}
+
+/// Like `map`, but only for the side effects. Returns the empty list.
+pub fn risp_for_each(args: &[RispExp], env: &mut RispEnv) -> Result<RispExp, RispErr> {
+    Arity::AtLeast(2).check("for-each", args)?;
+    for row in zip_lists(&args[1..])? {
+        apply(&args[0], &row, env)?;
+    }
+    Ok(RispExp::List(vec![]))
+}
+
+pub fn risp_filter(args: &[RispExp], env: &mut RispEnv) -> Result<RispExp, RispErr> {
+    Arity::Exactly(2).check("filter", args)?;
+    let mut out = vec![];
+    for item in args[1].as_list()? {
+        match apply(&args[0], std::slice::from_ref(item), env)? {
+            RispExp::Bool(true) => out.push(item.clone()),
+            RispExp::Bool(false) => {},
+            other => return Err(RispErr::type_error("a boolean", &other)),
+        }
+    }
+    Ok(RispExp::List(out))
+}
+
+/// `(fold f init list)`, left to right, calling `(f acc x)`.
+pub fn risp_fold(args: &[RispExp], env: &mut RispEnv) -> Result<RispExp, RispErr> {
+    Arity::Exactly(3).check("fold", args)?;
+    let mut acc = args[1].clone();
+    for item in args[2].as_list()? {
+        acc = apply(&args[0], &[acc, item.clone()], env)?;
+    }
+    Ok(acc)
+}
+
+/// `(reduce f list)`, `fold` starting from the first element.
+pub fn risp_reduce(args: &[RispExp], env: &mut RispEnv) -> Result<RispExp, RispErr> {
+    Arity::Exactly(2).check("reduce", args)?;
+    let (first, rest) = match args[1].as_list()?.split_first() {
+        Some(split) => split,
+        None => return Err(RispErr::Reason("can't reduce an empty list".to_string())),
+    };
+    let mut acc = first.clone();
+    for item in rest {
+        acc = apply(&args[0], &[acc, item.clone()], env)?;
+    }
+    Ok(acc)
+}
+
+/// `(apply f arg... list)`, call `f` with the args followed by the elements
+/// of `list`.
+pub fn risp_apply(args: &[RispExp], env: &mut RispEnv) -> Result<RispExp, RispErr> {
+    Arity::AtLeast(2).check("apply", args)?;
+    let (list, leading) = args[1..].split_last().expect("arity checked above");
+    let mut call_args = leading.to_vec();
+    call_args.extend_from_slice(list.as_list()?);
+    apply(&args[0], &call_args, env)
+}
+
+/// `(zip list...)`, a list of lists pairing up the elements of each list.
+pub fn risp_zip(args: &[RispExp], _env: &mut RispEnv) -> Result<RispExp, RispErr> {
+    Arity::AtLeast(1).check("zip", args)?;
+    Ok(RispExp::List(zip_lists(args)?.into_iter().map(RispExp::List).collect()))
+}
+
+/// Special forms, arithmetic, comparison, strings, lists and `error`;
+/// everything a program needs regardless of which other sets are installed.
+pub fn core_builtins(env: &mut RispEnv) {
+    env.define_special_form("if", risp_if as RispFunc);
+    env.set_help("if", "(if pred then [else]) evaluate `then` if `pred` is true, otherwise `else`");
+    env.define_special_form("cond", risp_cond as RispFunc);
+    env.set_help("cond", "(cond (pred exp...)... (else exp...)) evaluate the first clause whose `pred` is true");
+    env.define_special_form("when", risp_when as RispFunc);
+    env.set_help("when", "(when pred exp...) evaluate the expressions if `pred` is true");
+    env.define_special_form("unless", risp_unless as RispFunc);
+    env.set_help("unless", "(unless pred exp...) evaluate the expressions if `pred` is false");
+    env.define_special_form("case", risp_case as RispFunc);
+    env.set_help("case", "(case key ((datum...) exp...)... (else exp...)) evaluate the clause listing `key`");
+    env.define_special_form("and", risp_and as RispFunc);
+    env.set_help("and", "(and pred...) true if every `pred` is, stops at the first false one");
+    env.define_special_form("or", risp_or as RispFunc);
+    env.set_help("or", "(or pred...) true if any `pred` is, stops at the first true one");
+    env.define_special_form("begin", risp_begin as RispFunc);
+    env.set_help("begin", "(begin exp...) evaluate each expression in turn, returning the last value");
+    env.define_special_form("do", risp_do as RispFunc);
+    env.set_help("do", "(do exp...) same as `begin`");
+    env.define_special_form("let", risp_let as RispFunc);
+    env.set_help("let", "(let name exp) bind `name` to the value of `exp` in the current scope");
+    env.define_special_form("set!", risp_set as RispFunc);
+    env.set_help("set!", "(set! name exp) rebind `name` where it was defined");
+    env.define_special_form("fn", risp_lambda as RispFunc);
+    env.set_help("fn", "(fn (param...) body) create a lambda");
+    env.define_special_form("quote", risp_quote as RispFunc);
+    env.set_help("quote", "(quote x) or 'x, `x` itself without evaluating it");
+    env.define_special_form("quasiquote", risp_quasiquote as RispFunc);
+    env.set_help("quasiquote", "(quasiquote x) or `x, like quote but evaluating ,y and splicing ,@list");
+    env.define_special_form("unquote", risp_unquote as RispFunc);
+    env.set_help("unquote", "(unquote x) or ,x, inside a quasiquote evaluate `x`");
+    env.define_special_form("unquote-splicing", risp_unquote_splicing as RispFunc);
+    env.set_help("unquote-splicing", "(unquote-splicing list) or ,@list, inside a quasiquote splice the list in");
+    env.define_procedure("error", risp_error as RispFunc);
+    env.set_help("error", "(error value) raise `value` as an error");
+    env.define_procedure("not", risp_not as RispFunc);
+    env.set_help("not", "(not x) true if `x` is false");
+    env.define_procedure("+", risp_add as RispFunc);
+    env.set_help("+", "(+ x...) sum of the arguments");
+    env.define_procedure("-", risp_subtract as RispFunc);
+    env.set_help("-", "(- x y...) `x` minus the rest of the arguments");
+    env.define_procedure("*", risp_multiply as RispFunc);
+    env.set_help("*", "(* x...) product of the arguments");
+    env.define_procedure("/", risp_divide as RispFunc);
+    env.set_help("/", "(/ x y) `x` divided by `y`");
+    env.define_procedure("=", risp_eq as RispFunc);
+    env.set_help("=", "(= x y...) true if all the arguments are equal");
+    env.define_procedure("!=", risp_neq as RispFunc);
+    env.set_help("!=", "(!= x y...) true if any argument differs from `x`");
+    env.define_procedure(">", risp_gt as RispFunc);
+    env.set_help(">", "(> x y...) true if `x` is greater than each of the rest");
+    env.define_procedure(">=", risp_gte as RispFunc);
+    env.set_help(">=", "(>= x y...) true if `x` is greater than or equal to each of the rest");
+    env.define_procedure("<", risp_lt as RispFunc);
+    env.set_help("<", "(< x y...) true if `x` is less than each of the rest");
+    env.define_procedure("<=", risp_lte as RispFunc);
+    env.set_help("<=", "(<= x y...) true if `x` is less than or equal to each of the rest");
+    env.define_procedure("string-append", risp_string_append as RispFunc);
+    env.set_help("string-append", "(string-append s...) concatenate strings");
+    env.define_procedure("string-length", risp_string_length as RispFunc);
+    env.set_help("string-length", "(string-length s) number of characters in `s`");
+    env.define_procedure("substring", risp_substring as RispFunc);
+    env.set_help("substring", "(substring s start [end]) characters `start` up to `end` of `s`");
+    env.define_procedure("string-split", risp_string_split as RispFunc);
+    env.set_help("string-split", "(string-split s sep) split `s` on `sep`, or on whitespace if `sep` is empty");
+    env.define_procedure("string-join", risp_string_join as RispFunc);
+    env.set_help("string-join", "(string-join list sep) join a list of strings with `sep` between them");
+    env.define_procedure("string-upcase", risp_string_upcase as RispFunc);
+    env.set_help("string-upcase", "(string-upcase s) `s` in upper case");
+    env.define_procedure("string-downcase", risp_string_downcase as RispFunc);
+    env.set_help("string-downcase", "(string-downcase s) `s` in lower case");
+    env.define_procedure("string-trim", risp_string_trim as RispFunc);
+    env.set_help("string-trim", "(string-trim s) `s` without leading and trailing whitespace");
+    env.define_procedure("string-contains?", risp_string_contains as RispFunc);
+    env.set_help("string-contains?", "(string-contains? s needle) true if `needle` occurs in `s`");
+    env.define_procedure("number->string", risp_number_to_string as RispFunc);
+    env.set_help("number->string", "(number->string x) format a number as a string");
+    env.define_procedure("string->number", risp_string_to_number as RispFunc);
+    env.set_help("string->number", "(string->number s) parse a number, or false if `s` isn't one");
+    env.define_variable("nil", &RispExp::List(vec![]));
+    env.define_procedure("list", risp_list as RispFunc);
+    env.set_help("list", "(list x...) a list of the arguments");
+    env.define_procedure("cons", risp_cons as RispFunc);
+    env.set_help("cons", "(cons x list) `list` with `x` added to the front");
+    env.define_procedure("car", risp_car as RispFunc);
+    env.set_help("car", "(car list) or (first list), the first element of `list`");
+    env.define_procedure("first", risp_first as RispFunc);
+    env.set_help("first", "(car list) or (first list), the first element of `list`");
+    env.define_procedure("cdr", risp_cdr as RispFunc);
+    env.set_help("cdr", "(cdr list) or (rest list), `list` without its first element");
+    env.define_procedure("rest", risp_rest as RispFunc);
+    env.set_help("rest", "(cdr list) or (rest list), `list` without its first element");
+    env.define_procedure("nth", risp_nth as RispFunc);
+    env.set_help("nth", "(nth list i) the element of `list` at zero based index `i`");
+    env.define_procedure("append", risp_append as RispFunc);
+    env.set_help("append", "(append list...) the elements of every list, in order");
+    env.define_procedure("length", risp_length as RispFunc);
+    env.set_help("length", "(length list) the number of elements in `list`");
+    env.define_procedure("reverse", risp_reverse as RispFunc);
+    env.set_help("reverse", "(reverse list) `list` in reverse order");
+    env.define_procedure("empty?", risp_is_empty as RispFunc);
+    env.set_help("empty?", "(empty? x) or (null? x), true if `x` is the empty list");
+    env.define_procedure("null?", risp_is_null as RispFunc);
+    env.set_help("null?", "(empty? x) or (null? x), true if `x` is the empty list");
+    env.define_procedure("map", risp_map as RispFunc);
+    env.set_help("map", "(map f list...) call `f` on the elements of the lists in step, collecting the results");
+    env.define_procedure("for-each", risp_for_each as RispFunc);
+    env.set_help("for-each", "(for-each f list...) like map, but only for the side effects");
+    env.define_procedure("filter", risp_filter as RispFunc);
+    env.set_help("filter", "(filter pred list) the elements of `list` for which `pred` is true");
+    env.define_procedure("fold", risp_fold as RispFunc);
+    env.set_help("fold", "(fold f init list) combine the elements with `(f acc x)`, starting from `init`");
+    env.define_procedure("reduce", risp_reduce as RispFunc);
+    env.set_help("reduce", "(reduce f list) like fold, starting from the first element");
+    env.define_procedure("apply", risp_apply as RispFunc);
+    env.set_help("apply", "(apply f arg... list) call `f` with the args and the elements of `list`");
+    env.define_procedure("zip", risp_zip as RispFunc);
+    env.set_help("zip", "(zip list...) lists of the elements at each position, up to the shortest list");
+}
+
+/// `pi` and the floating point math functions.
+pub fn math_builtins(env: &mut RispEnv) {
+    env.define_variable("pi", &RispExp::Number(f64::consts::PI));
+    env.register_fn("cos", f64::cos);
+    env.set_help("cos", "(cos x) cosine of `x` radians");
+    env.register_fn("sin", f64::sin);
+    env.set_help("sin", "(sin x) sine of `x` radians");
+    env.register_fn("tan", f64::tan);
+    env.set_help("tan", "(tan x) tangent of `x` radians");
+    env.register_fn("acos", f64::acos);
+    env.set_help("acos", "(acos x) arc cosine of `x`, in radians");
+    env.register_fn("asin", f64::asin);
+    env.set_help("asin", "(asin x) arc sine of `x`, in radians");
+    env.register_fn("atan", f64::atan);
+    env.set_help("atan", "(atan x) arc tangent of `x`, in radians");
+    env.register_fn("log", f64::ln);
+    env.set_help("log", "(log x) natural logarithm of `x`");
+    env.register_fn("log2", f64::log2);
+    env.set_help("log2", "(log2 x) base 2 logarithm of `x`");
+    env.register_fn("log10", f64::log10);
+    env.set_help("log10", "(log10 x) base 10 logarithm of `x`");
+    env.register_fn("sqrt", f64::sqrt);
+    env.set_help("sqrt", "(sqrt x) square root of `x`");
+    env.register_fn("exp", f64::exp);
+    env.set_help("exp", "(exp x) e raised to the power `x`");
+    env.register_fn("abs", f64::abs);
+    env.set_help("abs", "(abs x) absolute value of `x`");
+    env.register_fn("pow", f64::powf);
+    env.set_help("pow", "(pow x y) `x` raised to the power `y`");
+}
+
+/// Builtins with side effects outside the interpreter.
+pub fn io_builtins(env: &mut RispEnv) {
+    env.define_procedure("println", risp_println as RispFunc);
+    env.set_help("println", "(println value...) print the values separated by spaces");
+}
+
+pub fn standard_env() -> RispEnv {
+    let mut env = RispEnv::default();
+    core_builtins(&mut env);
+    math_builtins(&mut env);
+    io_builtins(&mut env);
+    env
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::f64;
+
+    #[test]
+    fn test_add() {
+        let expr = "(+ 10 5)";
+        let mut env = standard_env();
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, RispExp::Number(15_f64));
+
+        let expr = "(+ 10 5 3 1 -12)";
+        let mut env = standard_env();
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, RispExp::Number(7_f64));
+
+        let expr = "(+ 10 (+ 5 (+ 1 2)) 1 -12)";
+        let mut env = standard_env();
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, RispExp::Number(7_f64));
+    }
+
+    #[test]
+    fn test_subtract() {
+        let expr = "(- 10 5)";
+        let mut env = standard_env();
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, RispExp::Number(5_f64));
+
+        let expr = "(- 10 (- 8 3) 3 1 -12)";
+        let mut env = standard_env();
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, RispExp::Number(13_f64));
+    }
+
+    #[test]
+    fn test_multiply() {
+        let expr = "(* 10 5)";
+        let mut env = standard_env();
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, RispExp::Number(50_f64));
+
+        let expr = "(* 10 (- 8 3) 3 1)";
+        let mut env = standard_env();
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, RispExp::Number(150_f64));
+    }
+
+    #[test]
+    fn test_divide() {
+        let expr = "(/ 10 5)";
+        let mut env = standard_env();
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, RispExp::Number(2_f64));
+
+        let expr = "(/ 150 (- 8 3))";
+        let mut env = standard_env();
         let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
         assert_eq!(output, RispExp::Number(30_f64));
     }
@@ -605,7 +1287,7 @@ mod tests {
         let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
         assert_eq!(output, RispExp::Number(1.0));
 
-        let expr = "(if (= 10 10 10) asdf 1)";
+        let expr = "(if (= 10 10 10) 'asdf 1)";
         let mut env = standard_env();
         let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
         assert_eq!(output, RispExp::Symbol("asdf".to_string()));
@@ -652,8 +1334,7 @@ mod tests {
         let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
         assert_eq!(output, RispExp::Number(5.0));
 
-        let mut inner_env = RispEnv::new();
-        inner_env.outer = Some(&env);
+        let mut inner_env = RispEnv::with_outer(&env);
         let expr = "(let a 3)";
         let output = eval(parse(expr).expect("failed to parse"), &mut inner_env).expect("failed to eval");
         assert_eq!(output, RispExp::Number(3.0));
@@ -677,4 +1358,821 @@ mod tests {
         let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
         assert_eq!(output, RispExp::Number(5.3));
     }
+
+    #[test]
+    fn test_lambda_args_evaluated() {
+        let mut env = standard_env();
+        let expr = "(let addone (fn (x) (+ x 1)))";
+        eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+
+        let expr = "(addone (+ 1 2))";
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, RispExp::Number(4.0));
+
+        let expr = "(let y 10)";
+        eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+
+        let expr = "(addone (addone y))";
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, RispExp::Number(12.0));
+    }
+
+    #[test]
+    fn test_closure_adder() {
+        let mut env = standard_env();
+        let expr = "(let make-adder (fn (n) (fn (x) (+ x n))))";
+        eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+
+        let expr = "(let add5 (make-adder 5))";
+        eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+
+        let expr = "(let add10 (make-adder (* 2 5)))";
+        eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+
+        let expr = "(add5 1)";
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, RispExp::Number(6.0));
+
+        let expr = "(add10 1)";
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, RispExp::Number(11.0));
+
+        // `n` only exists inside the closures, not in the caller's scope
+        let expr = "n";
+        let output = eval(parse(expr).expect("failed to parse"), &mut env);
+        assert_eq!(output, Err(RispErr::Unbound { name: "n".to_string(), suggestions: vec![] }));
+    }
+
+    #[test]
+    fn test_closure_shadowing() {
+        let mut env = standard_env();
+        let expr = "(let x 1)";
+        eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+
+        let expr = "(let shadow (fn (x) (+ x 10)))";
+        eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+
+        let expr = "(shadow 5)";
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, RispExp::Number(15.0));
+
+        let expr = "x";
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, RispExp::Number(1.0));
+
+        // Lexical scope: the captured `x` wins over the caller's
+        let expr = "(let get-x (fn () x))";
+        eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+
+        let expr = "(let call-with-x (fn (x) (get-x)))";
+        eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+
+        let expr = "(call-with-x 99)";
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, RispExp::Number(1.0));
+    }
+
+    #[test]
+    fn test_recursive_lambda() {
+        let mut env = standard_env();
+        let expr = "(let fact (fn (n) (if (> n 1) (* n (fact (- n 1))) 1)))";
+        eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+
+        let expr = "(fact 5)";
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, RispExp::Number(120.0));
+    }
+
+    #[test]
+    fn test_shared_env() {
+        let mut env = standard_env();
+        let expr = "(let count 0)";
+        eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+
+        let expr = "(let get-count (fn () count))";
+        eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+
+        // The lambda shares the global scope, so it sees later rebinding
+        let expr = "(let count 5)";
+        eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+
+        let expr = "(get-count)";
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, RispExp::Number(5.0));
+
+        // Clones are handles to the same scope
+        let mut handle = env.clone();
+        handle.define_variable("count", &RispExp::Number(7.0));
+        assert_eq!(env.get("count"), Some(RispExp::Number(7.0)));
+
+        // Inner scopes don't leak into their outer scope
+        let mut inner_env = RispEnv::with_outer(&env);
+        inner_env.define_variable("hidden", &RispExp::Number(1.0));
+        assert_eq!(inner_env.get("count"), Some(RispExp::Number(7.0)));
+        assert_eq!(env.get("hidden"), None);
+    }
+
+    #[test]
+    fn test_mutual_recursion() {
+        let mut env = standard_env();
+        let expr = "(let even (fn (n) (if (> n 0) (odd (- n 1)) true)))";
+        eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+
+        let expr = "(let odd (fn (n) (if (> n 0) (even (- n 1)) false)))";
+        eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+
+        let expr = "(even 10)";
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, RispExp::Bool(true));
+
+        let expr = "(odd 7)";
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, RispExp::Bool(true));
+    }
+
+    #[test]
+    fn test_set() {
+        let mut env = standard_env();
+        let expr = "(let total 0)";
+        eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+
+        let expr = "(let accumulate (fn (x) (set! total (+ total x))))";
+        eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+
+        let expr = "(accumulate 3)";
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, RispExp::Number(3.0));
+
+        let expr = "(accumulate 4)";
+        eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+
+        let expr = "total";
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, RispExp::Number(7.0));
+
+        // `set!` rebinds the variable where it lives, parameters included
+        let expr = "(let bump (fn (total) (set! total (+ total 100))))";
+        eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+
+        let expr = "(bump 1)";
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, RispExp::Number(101.0));
+
+        let expr = "total";
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, RispExp::Number(7.0));
+
+        let expr = "(set! undefined-thing 1)";
+        let output = eval(parse(expr).expect("failed to parse"), &mut env);
+        assert_eq!(output, Err(RispErr::Unbound { name: "undefined-thing".to_string(), suggestions: vec![] }));
+    }
+
+    #[test]
+    fn test_set_counter() {
+        let mut env = standard_env();
+        let expr = "(let make-counter (fn (count) (fn () (set! count (+ count 1)))))";
+        eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+
+        let expr = "(let counter-a (make-counter 0))";
+        eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+
+        let expr = "(let counter-b (make-counter 10))";
+        eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+
+        let expr = "(counter-a)";
+        eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+
+        let expr = "(counter-a)";
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, RispExp::Number(2.0));
+
+        let expr = "(counter-b)";
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, RispExp::Number(11.0));
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut env = standard_env();
+        let expr = "(cos 1 2)";
+        let output = eval(parse(expr).expect("failed to parse"), &mut env);
+        assert_eq!(output, Err(RispErr::Arity { name: "cos".to_string(), expected: Arity::Exactly(1), found: 2 }));
+
+        let expr = "(pow)";
+        let output = eval(parse(expr).expect("failed to parse"), &mut env);
+        assert_eq!(output, Err(RispErr::Arity { name: "pow".to_string(), expected: Arity::Exactly(2), found: 0 }));
+
+        let expr = "(< 1)";
+        let output = eval(parse(expr).expect("failed to parse"), &mut env);
+        assert_eq!(output, Err(RispErr::Arity { name: "<".to_string(), expected: Arity::AtLeast(2), found: 1 }));
+
+        let expr = "(+ 1 (> 2 1))";
+        let output = eval(parse(expr).expect("failed to parse"), &mut env);
+        assert_eq!(output, Err(RispErr::Type { expected: "a number", found: RispExp::Bool(true) }));
+
+        let expr = "(/ 1 (- 2 2))";
+        let output = eval(parse(expr).expect("failed to parse"), &mut env);
+        assert_eq!(output, Err(RispErr::DivideByZero));
+
+        let expr = "(not-a-function 1)";
+        let output = eval(parse(expr).expect("failed to parse"), &mut env);
+        assert_eq!(output, Err(RispErr::Unbound { name: "not-a-function".to_string(), suggestions: vec![] }));
+
+        let expr = "(pi 1)";
+        let output = eval(parse(expr).expect("failed to parse"), &mut env);
+        assert_eq!(output, Err(RispErr::Type { expected: "a procedure", found: RispExp::Number(f64::consts::PI) }));
+
+        let expr = "(error (* 6 7))";
+        let output = eval(parse(expr).expect("failed to parse"), &mut env);
+        assert_eq!(output, Err(RispErr::User(RispExp::Number(42.0))));
+
+        let expr = "(let twice (fn (x) (* 2 x)))";
+        eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+
+        let expr = "(twice 1 2)";
+        let output = eval(parse(expr).expect("failed to parse"), &mut env);
+        assert_eq!(output, Err(RispErr::Arity { name: "lambda".to_string(), expected: Arity::Exactly(1), found: 2 }));
+
+        // The form the error happened in rides along as the error's source
+        let err = output.expect_err("should fail");
+        assert!(matches!(err.root(), RispErr::Arity { .. }));
+        let source = std::error::Error::source(&err).expect("missing source");
+        assert_eq!(source.to_string(), "Error: `lambda` takes exactly 1 argument, got 2");
+    }
+
+    #[test]
+    fn test_compare_evaluates_arguments() {
+        let mut env = standard_env();
+        let expr = "(let a 3)";
+        eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+
+        let expr = "(= a 3 (+ 1 2))";
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, RispExp::Bool(true));
+
+        let expr = "(< a 4 (* a 2))";
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, RispExp::Bool(true));
+
+        let expr = "(<= (- a 3) 0 a)";
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, RispExp::Bool(true));
+    }
+
+    #[test]
+    fn test_strings() {
+        let mut env = standard_env();
+        let expr = r#"(let greeting (string-append "hello" ", " "world"))"#;
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, RispExp::Str("hello, world".to_string()));
+
+        let expr = r#"(string-length "héllo")"#;
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, RispExp::Number(5.0));
+
+        let expr = "(substring greeting 7)";
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, RispExp::Str("world".to_string()));
+
+        let expr = "(substring greeting 0 5)";
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, RispExp::Str("hello".to_string()));
+
+        let expr = "(substring greeting 3 100)";
+        assert!(eval(parse(expr).expect("failed to parse"), &mut env).is_err());
+
+        let expr = r#"(string-split "a,b,,c" ",")"#;
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, RispExp::List(vec![
+            RispExp::Str("a".to_string()),
+            RispExp::Str("b".to_string()),
+            RispExp::Str("".to_string()),
+            RispExp::Str("c".to_string()),
+        ]));
+
+        let expr = r#"(string-join (string-split "  lots   of space " "") "-")"#;
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, RispExp::Str("lots-of-space".to_string()));
+
+        let expr = "(string-upcase greeting)";
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, RispExp::Str("HELLO, WORLD".to_string()));
+
+        let expr = r#"(string-downcase "MiXeD")"#;
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, RispExp::Str("mixed".to_string()));
+
+        let expr = r#"(string-trim "\t padded \n")"#;
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, RispExp::Str("padded".to_string()));
+
+        let expr = r#"(string-contains? greeting "lo, w")"#;
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, RispExp::Bool(true));
+
+        let expr = "(string-append (number->string (* 2 21)) (number->string 0.5))";
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, RispExp::Str("420.5".to_string()));
+
+        let expr = "(number->string 1e300)";
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, RispExp::Str(RispExp::Number(1e300).to_string()));
+        assert_eq!(output, RispExp::Str("1e300".to_string()));
+
+        let expr = r#"(+ 1 (string->number " 2.5 "))"#;
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, RispExp::Number(3.5));
+
+        let expr = r#"(string->number "nope")"#;
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, RispExp::Bool(false));
+
+        let expr = r#"(= "abc" (string-downcase "ABC"))"#;
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, RispExp::Bool(true));
+
+        let expr = r#"(string-length 5)"#;
+        let output = eval(parse(expr).expect("failed to parse"), &mut env);
+        assert_eq!(output, Err(RispErr::Type { expected: "a string", found: RispExp::Number(5.0) }));
+    }
+
+    #[test]
+    fn test_begin() {
+        let expr = "(begin (let r 10) (* pi (* r r)))";
+        let mut env = standard_env();
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, RispExp::Number(f64::consts::PI * 100.0));
+
+        let expr = "(let count-up (fn (n) (do (set! n (+ n 1)) (set! n (+ n 1)) n)))";
+        eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+
+        let expr = "(count-up 1)";
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, RispExp::Number(3.0));
+
+        let expr = "(begin)";
+        let output = eval(parse(expr).expect("failed to parse"), &mut env);
+        assert_eq!(output, Err(RispErr::Arity { name: "begin".to_string(), expected: Arity::AtLeast(1), found: 0 }));
+
+        let expr = "(do)";
+        let output = eval(parse(expr).expect("failed to parse"), &mut env);
+        assert_eq!(output, Err(RispErr::Arity { name: "do".to_string(), expected: Arity::AtLeast(1), found: 0 }));
+    }
+
+    #[test]
+    fn test_builtins_as_values() {
+        let mut env = standard_env();
+        let expr = "(let f +)";
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output.type_name(), "procedure");
+        assert_eq!(output.to_string(), "#<builtin +>");
+
+        let expr = "(f 1 2)";
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, RispExp::Number(3.0));
+
+        // Builtins and lambdas can be passed to the same higher order code
+        let expr = "(let twice (fn (g x) (g (g x))))";
+        eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+
+        let expr = "(twice sqrt 16)";
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, RispExp::Number(2.0));
+
+        let expr = "(twice (fn (x) (* x 3)) 2)";
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, RispExp::Number(18.0));
+
+        // Arguments reach builtins already evaluated
+        let expr = "(let s \"abc\")";
+        eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+
+        let expr = "(string-length s)";
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, RispExp::Number(3.0));
+
+        let expr = "(= + +)";
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, RispExp::Bool(true));
+    }
+
+    #[test]
+    fn test_operator_expressions() {
+        let mut env = standard_env();
+        let expr = "((fn (x) (* x x)) 4)";
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, RispExp::Number(16.0));
+
+        let expr = "(let pick-op (fn () -))";
+        eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+
+        let expr = "((pick-op) 1 2)";
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, RispExp::Number(-1.0));
+
+        let expr = "(let make-adder (fn (n) (fn (x) (+ x n))))";
+        eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+
+        let expr = "((make-adder 10) 5)";
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, RispExp::Number(15.0));
+
+        // Non-callable values in operator position are type errors
+        let expr = "(1 2 3)";
+        let err = eval(parse(expr).expect("failed to parse"), &mut env).unwrap_err();
+        assert_eq!(err, RispErr::type_error("a procedure", &RispExp::Number(1.0)));
+
+        let expr = "((+ 1 2) 4)";
+        let err = eval(parse(expr).expect("failed to parse"), &mut env).unwrap_err();
+        assert_eq!(err, RispErr::type_error("a procedure", &RispExp::Number(3.0)));
+    }
+
+    #[test]
+    fn test_clear() {
+        use std::cell::Cell;
+        use std::rc::Rc;
+
+        struct SetOnDrop(Rc<Cell<bool>>);
+        impl Drop for SetOnDrop {
+            fn drop(&mut self) {
+                self.0.set(true);
+            }
+        }
+
+        let dropped = Rc::new(Cell::new(false));
+        let mut env = standard_env();
+        let handle = SetOnDrop(dropped.clone());
+        env.define_closure("handle", move |_args, _env| {
+            let _ = &handle;
+            Ok(RispExp::List(vec![]))
+        });
+        // The lambda refers back to `env`, so dropping `env` alone won't
+        // free it
+        let expr = "(let f (fn (x) x))";
+        eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+
+        assert!(!dropped.get());
+        env.clear();
+        assert!(dropped.get());
+        assert_eq!(env.get("f"), None);
+        assert!(env.symbols().is_empty());
+    }
+
+    #[test]
+    fn test_define_closure() {
+        use std::cell::Cell;
+        use std::rc::Rc;
+
+        let mut env = standard_env();
+        let calls = Rc::new(Cell::new(0));
+        let counter = calls.clone();
+        env.define_closure("tick", move |args, _env| {
+            Arity::Exactly(0).check("tick", args)?;
+            counter.set(counter.get() + 1);
+            Ok(RispExp::Number(counter.get() as f64))
+        });
+
+        let expr = "(tick)";
+        eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, RispExp::Number(2.0));
+        assert_eq!(calls.get(), 2);
+
+        // Closures are values like any other builtin
+        let expr = "(let t tick)";
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output.to_string(), "#<builtin tick>");
+
+        let expr = "(t)";
+        eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(calls.get(), 3);
+
+        let scale = 2.5;
+        env.define_closure("scale", move |args, _env| {
+            Arity::Exactly(1).check("scale", args)?;
+            Ok(RispExp::Number(args[0].as_number()? * scale))
+        });
+        let expr = "(scale 4)";
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, RispExp::Number(10.0));
+    }
+
+    #[test]
+    fn test_quote() {
+        let mut env = standard_env();
+        let expr = "(quote (+ 1 2))";
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, parse("(+ 1 2)").expect("failed to parse"));
+
+        let expr = "'undefined-symbol";
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, RispExp::Symbol("undefined-symbol".to_string()));
+
+        let expr = "'()";
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, RispExp::List(vec![]));
+
+        // Quoted code can be built up and evaluated later
+        let expr = "(let code '(* 6 7))";
+        eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        let code = env.get("code").expect("code is bound");
+        assert_eq!(eval(code, &mut env), Ok(RispExp::Number(42.0)));
+    }
+
+    #[test]
+    fn test_quasiquote() {
+        let mut env = standard_env();
+        let expr = "(let x 5)";
+        eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+
+        let expr = "`(x ,x ,(+ x 1))";
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, parse("(x 5 6)").expect("failed to parse"));
+
+        let expr = "`(1 ,@(string-split \"a b\" \"\") 2)";
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, parse("(1 \"a\" \"b\" 2)").expect("failed to parse"));
+
+        // Only the outermost level of a nested quasiquote is expanded
+        let expr = "`(a `(b ,(c ,x)))";
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, parse("(a `(b ,(c 5)))").expect("failed to parse"));
+
+        let expr = "`(,@x)";
+        let err = eval(parse(expr).expect("failed to parse"), &mut env).unwrap_err();
+        assert_eq!(err, RispErr::type_error("a list", &RispExp::Number(5.0)));
+
+        let expr = ",x";
+        let err = eval(parse(expr).expect("failed to parse"), &mut env).unwrap_err();
+        assert_eq!(err, RispErr::Reason("`unquote` used outside of `quasiquote`".to_string()));
+
+        let expr = "`,@'(1 2)";
+        let err = eval(parse(expr).expect("failed to parse"), &mut env).unwrap_err();
+        assert_eq!(err, RispErr::Reason("`unquote-splicing` must appear inside a list".to_string()));
+    }
+
+    #[test]
+    fn test_unbound_symbols() {
+        let mut env = standard_env();
+        let expr = "(+ piee 1)";
+        let err = eval(parse(expr).expect("failed to parse"), &mut env).unwrap_err();
+        assert_eq!(err, RispErr::Unbound { name: "piee".to_string(), suggestions: vec!["pi".to_string()] });
+        assert_eq!(err.to_string(), "Error: unbound symbol `piee`, did you mean `pi`?");
+
+        let expr = "(string-lenght \"abc\")";
+        let err = eval(parse(expr).expect("failed to parse"), &mut env).unwrap_err();
+        assert_eq!(err.to_string(), "Error: unbound symbol `string-lenght`, did you mean `string-length`?");
+
+        let expr = "(let counter 0)";
+        eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        let expr = "(let counted 0)";
+        eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        let expr = "(set! countr 1)";
+        let err = eval(parse(expr).expect("failed to parse"), &mut env).unwrap_err();
+        assert_eq!(err.to_string(), "Error: unbound symbol `countr`, did you mean `counter` or `counted`?");
+
+        let expr = "zzzzzz";
+        let err = eval(parse(expr).expect("failed to parse"), &mut env).unwrap_err();
+        assert_eq!(err.to_string(), "Error: unbound symbol `zzzzzz`");
+    }
+
+    #[test]
+    fn test_lenient_symbols() {
+        let mut env = standard_env();
+        let expr = "(let f (fn () zyzzyva))";
+        eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+
+        // The switch also applies inside closures created earlier
+        env.set_lenient_symbols(true);
+        assert!(env.lenient_symbols());
+        let expr = "(f)";
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, RispExp::Symbol("zyzzyva".to_string()));
+
+        env.set_lenient_symbols(false);
+        let err = eval(parse(expr).expect("failed to parse"), &mut env).unwrap_err();
+        assert_eq!(err, RispErr::Unbound { name: "zyzzyva".to_string(), suggestions: vec![] });
+    }
+
+    #[test]
+    fn test_lists() {
+        let mut env = standard_env();
+        let cases = [
+            ("(list 1 2 3)", "(1 2 3)"),
+            ("(list)", "()"),
+            ("()", "()"),
+            ("nil", "()"),
+            ("(cons 0 (list 1 2))", "(0 1 2)"),
+            ("(cons 0 nil)", "(0)"),
+            ("(car '(a b))", "a"),
+            ("(first '(a b))", "a"),
+            ("(cdr '(a b c))", "(b c)"),
+            ("(rest '(a))", "()"),
+            ("(nth '(a b c) 2)", "c"),
+            ("(append '(1 2) nil '(3) '(4 5))", "(1 2 3 4 5)"),
+            ("(append)", "()"),
+            ("(length '(1 (2 3) 4))", "3"),
+            ("(reverse '(1 2 3))", "(3 2 1)"),
+            ("(empty? nil)", "true"),
+            ("(null? (list 1))", "false"),
+            ("(empty? 0)", "false"),
+            ("(car (cdr (list (+ 1 1) (* 2 2))))", "4"),
+        ];
+        for (expr, expected) in cases.iter() {
+            let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+            assert_eq!(output, parse(expected).expect("failed to parse"), "{}", expr);
+        }
+
+        // Recursive processing of a list
+        let expr = "(let sum (fn (xs) (if (empty? xs) 0 (+ (car xs) (sum (cdr xs))))))";
+        eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        let expr = "(sum '(1 2 3 4))";
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, RispExp::Number(10.0));
+
+        let expr = "(car nil)";
+        let err = eval(parse(expr).expect("failed to parse"), &mut env).unwrap_err();
+        assert_eq!(err, RispErr::Reason("can't take the first element of an empty list".to_string()));
+
+        // Aliases report errors under the name that was used
+        for (expr, name, found) in [("(first)", "first", 0), ("(rest)", "rest", 0), ("(null? 1 2)", "null?", 2)].iter() {
+            let err = eval(parse(expr).expect("failed to parse"), &mut env).unwrap_err();
+            assert_eq!(err, RispErr::Arity { name: name.to_string(), expected: Arity::Exactly(1), found: *found });
+        }
+
+        let expr = "(nth '(a b) 2)";
+        let err = eval(parse(expr).expect("failed to parse"), &mut env).unwrap_err();
+        assert_eq!(err, RispErr::Reason("index 2 is out of range for a list of length 2".to_string()));
+
+        let expr = "(cons 1 2)";
+        let err = eval(parse(expr).expect("failed to parse"), &mut env).unwrap_err();
+        assert_eq!(err, RispErr::type_error("a list", &RispExp::Number(2.0)));
+    }
+
+    #[test]
+    fn test_higher_order() {
+        let mut env = standard_env();
+        let cases = [
+            ("(map (fn (x) (* x x)) '(1 2 3))", "(1 4 9)"),
+            ("(map + '(1 2 3) '(10 20 30 40))", "(11 22 33)"),
+            ("(map sqrt nil)", "()"),
+            ("(filter (fn (x) (> x 2)) '(1 2 3 4))", "(3 4)"),
+            ("(fold - 0 '(1 2 3))", "-6"),
+            ("(fold (fn (acc x) (cons x acc)) nil '(1 2 3))", "(3 2 1)"),
+            ("(reduce + '(1 2 3 4))", "10"),
+            ("(reduce + '(5))", "5"),
+            ("(apply + '(1 2 3))", "6"),
+            ("(apply + 1 2 '(3))", "6"),
+            ("(apply (fn (a b) (- a b)) '(5 3))", "2"),
+            ("(zip '(1 2 3) '(a b))", "((1 a) (2 b))"),
+            ("(for-each println nil)", "()"),
+        ];
+        for (expr, expected) in cases.iter() {
+            let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+            assert_eq!(output, parse(expected).expect("failed to parse"), "{}", expr);
+        }
+
+        let expr = "(let total 0)";
+        eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        let expr = "(for-each (fn (x y) (set! total (+ total (* x y)))) '(1 2) '(3 4))";
+        eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(env.get("total"), Some(RispExp::Number(11.0)));
+
+        let expr = "(filter (fn (x) x) '(1))";
+        let err = eval(parse(expr).expect("failed to parse"), &mut env).unwrap_err();
+        assert_eq!(err, RispErr::type_error("a boolean", &RispExp::Number(1.0)));
+
+        let expr = "(reduce + nil)";
+        let err = eval(parse(expr).expect("failed to parse"), &mut env).unwrap_err();
+        assert_eq!(err, RispErr::Reason("can't reduce an empty list".to_string()));
+
+        let expr = "(map 1 '(1))";
+        let err = eval(parse(expr).expect("failed to parse"), &mut env).unwrap_err();
+        assert_eq!(err, RispErr::type_error("a procedure", &RispExp::Number(1.0)));
+    }
+
+    #[test]
+    fn test_conditionals() {
+        let mut env = standard_env();
+        let cases = [
+            ("(if false 1)", "()"),
+            ("(cond ((> 1 2) 'a) ((> 2 1) 'b) (else 'c))", "b"),
+            ("(cond ((> 1 2) 'a) (else 'c 'd))", "d"),
+            ("(cond ((> 1 2) 'a))", "()"),
+            ("(cond ((= 1 1)))", "true"),
+            ("(when (= 1 1) 'a 'b)", "b"),
+            ("(when (= 1 2) 'a)", "()"),
+            ("(unless (= 1 2) 'a)", "a"),
+            ("(unless (= 1 1) 'a)", "()"),
+            ("(case (* 2 3) ((2 3 5 7) 'prime) ((1 4 6 8 9) 'composite))", "composite"),
+            ("(case \"b\" ((\"a\") 1) (\"b\" 2))", "2"),
+            ("(case 'x ((a) 1) (else 'other))", "other"),
+            ("(case 0 ((1) 1))", "()"),
+            ("(and)", "true"),
+            ("(and (= 1 1) (> 2 1))", "true"),
+            ("(or (= 1 2) (> 2 1))", "true"),
+            ("(or)", "false"),
+            ("(not (= 1 2))", "true"),
+            ("(map not '(true false))", "(false true)"),
+        ];
+        for (expr, expected) in cases.iter() {
+            let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+            assert_eq!(output, parse(expected).expect("failed to parse"), "{}", expr);
+        }
+
+        // `and` and `or` stop as soon as the answer is known
+        let expr = "(and false (error \"not reached\"))";
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, RispExp::Bool(false));
+
+        let expr = "(or true (error \"not reached\"))";
+        let output = eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(output, RispExp::Bool(true));
+
+        // Malformed conditionals are errors rather than panics
+        let expr = "(if true)";
+        let err = eval(parse(expr).expect("failed to parse"), &mut env).unwrap_err();
+        assert_eq!(err, RispErr::Arity { name: "if".to_string(), expected: Arity::Between(2, 3), found: 1 });
+
+        let expr = "(if)";
+        assert!(eval(parse(expr).expect("failed to parse"), &mut env).is_err());
+
+        let expr = "(if (undefined) 1 2)";
+        assert!(eval(parse(expr).expect("failed to parse"), &mut env).is_err());
+
+        let expr = "(if 1 2 3)";
+        let err = eval(parse(expr).expect("failed to parse"), &mut env).unwrap_err();
+        assert_eq!(err, RispErr::type_error("a boolean", &RispExp::Number(1.0)));
+
+        let expr = "(when true)";
+        let err = eval(parse(expr).expect("failed to parse"), &mut env).unwrap_err();
+        assert_eq!(err, RispErr::Arity { name: "when".to_string(), expected: Arity::AtLeast(2), found: 1 });
+
+        let expr = "(cond (else 1) ((= 1 1) 2))";
+        let err = eval(parse(expr).expect("failed to parse"), &mut env).unwrap_err();
+        assert_eq!(err, RispErr::Reason("`else` must be the last cond clause".to_string()));
+
+        let expr = "(cond ())";
+        let err = eval(parse(expr).expect("failed to parse"), &mut env).unwrap_err();
+        assert_eq!(err, RispErr::type_error("a cond clause", &RispExp::List(vec![])));
+
+        let expr = "(and true 1)";
+        let err = eval(parse(expr).expect("failed to parse"), &mut env).unwrap_err();
+        assert_eq!(err, RispErr::type_error("a boolean", &RispExp::Number(1.0)));
+    }
+
+    #[test]
+    fn test_symbols() {
+        let mut env = standard_env();
+        env.define_variable("zeta", &RispExp::Number(1.0));
+        let mut inner_env = RispEnv::with_outer(&env);
+        inner_env.define_variable("alpha", &RispExp::Number(2.0));
+
+        let symbols = inner_env.symbols();
+        for name in ["alpha", "zeta", "pi", "cos", "string-append"].iter() {
+            assert!(symbols.iter().any(|s| s == name), "missing {}", name);
+        }
+        let mut sorted = symbols.clone();
+        sorted.sort();
+        sorted.dedup();
+        assert_eq!(symbols, sorted);
+
+        assert!(!env.symbols().iter().any(|s| s == "alpha"));
+    }
+
+    #[test]
+    fn test_builtin_help() {
+        // Every builtin in the standard environment is documented
+        let mut env = standard_env();
+        for name in env.symbols() {
+            let builtin = env.get_special_form(&name).is_some()
+                || matches!(env.get(&name), Some(RispExp::Proc(_)));
+            if builtin {
+                assert!(env.help(&name).is_some(), "`{}` has no help", name);
+            }
+        }
+        assert_eq!(env.help("pow"), Some("(pow x y) `x` raised to the power `y`".to_string()));
+        assert_eq!(env.help("no-such-builtin"), None);
+
+        // Builtins registered by the embedder can be described too
+        env.register_fn("hypot", |a: f64, b: f64| a.hypot(b));
+        env.set_help("hypot", "(hypot a b) length of the hypotenuse");
+        assert_eq!(env.help("hypot"), Some("(hypot a b) length of the hypotenuse".to_string()));
+
+        // Found from nested scopes, until something shadows the name
+        let mut inner = RispEnv::with_outer(&env);
+        assert_eq!(inner.help("hypot"), Some("(hypot a b) length of the hypotenuse".to_string()));
+        inner.define_variable("hypot", &RispExp::Number(1.0));
+        assert_eq!(inner.help("hypot"), None);
+        assert!(env.help("hypot").is_some());
+
+        // Rebinding forgets it
+        env.register_fn("hypot", |a: f64| a);
+        assert_eq!(env.help("hypot"), None);
+        env.define_special_form("if", env::risp_if as RispFunc);
+        assert_eq!(env.help("if"), None);
+        let expr = "(let pow (fn (x y) x))";
+        eval(parse(expr).expect("failed to parse"), &mut env).expect("failed to eval");
+        assert_eq!(env.help("pow"), None);
+    }
 }
@@ -0,0 +1,215 @@
+//! A high level entry point for embedding risp.
+
+use std::fs;
+use std::path::Path;
+
+use crate::env::{core_builtins, io_builtins, math_builtins};
+use crate::{apply, run_program, IntoRisp, RispEnv, RispErr, RispExp, RispFn};
+
+/// Which optional sets of builtins an `Interpreter` installs. The special
+/// forms, arithmetic, comparison and string builtins are always there.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Builtins {
+    /// `pi`, trigonometry, logarithms, `sqrt`, `pow` and friends.
+    pub math: bool,
+    /// `println`.
+    pub io: bool,
+    /// The comms-rs nodes and a graph to add them to.
+    #[cfg(feature = "comms-rs")]
+    pub comms: bool,
+}
+
+impl Builtins {
+    /// Only the core builtins, e.g. for sandboxed configuration scripts.
+    pub fn core() -> Self {
+        Self {
+            math: false,
+            io: false,
+            #[cfg(feature = "comms-rs")]
+            comms: false,
+        }
+    }
+}
+
+/// Everything `standard_env` has.
+impl Default for Builtins {
+    fn default() -> Self {
+        Self {
+            math: true,
+            io: true,
+            #[cfg(feature = "comms-rs")]
+            comms: false,
+        }
+    }
+}
+
+/// An interpreter that owns its global environment. Dropping it clears the
+/// environment, which frees lambdas and anything captured by registered
+/// closures.
+///
+/// ```
+/// let mut interp = risp::Interpreter::new();
+/// interp.eval_str("(let square (fn (x) (* x x)))").unwrap();
+/// let nine = interp.call_function("square", &[risp::RispExp::Number(3.0)]);
+/// assert_eq!(nine, Ok(risp::RispExp::Number(9.0)));
+/// ```
+#[derive(Debug)]
+pub struct Interpreter {
+    env: RispEnv,
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Self::with_builtins(Builtins::default())
+    }
+
+    pub fn with_builtins(builtins: Builtins) -> Self {
+        let mut env = RispEnv::new();
+        core_builtins(&mut env);
+        if builtins.math {
+            math_builtins(&mut env);
+        }
+        if builtins.io {
+            io_builtins(&mut env);
+        }
+        #[cfg(feature = "comms-rs")]
+        if builtins.comms {
+            crate::comms::comms_builtins(&mut env);
+        }
+        Self { env }
+    }
+
+    /// The global environment, for anything the facade doesn't cover. Any
+    /// handle cloned from it is cleared along with the interpreter.
+    pub fn env(&mut self) -> &mut RispEnv {
+        &mut self.env
+    }
+
+    /// Evaluate every expression in `source`, returning the last value.
+    pub fn eval_str(&mut self, source: &str) -> Result<RispExp, RispErr> {
+        run_program(source, &mut self.env)
+    }
+
+    pub fn eval_file<P: AsRef<Path>>(&mut self, path: P) -> Result<RispExp, RispErr> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path).map_err(|e| {
+            RispErr::Reason(format!("failed to read {}: {}", path.display(), e))
+        })?;
+        self.eval_str(&source)
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<RispExp> {
+        self.env.get(name)
+    }
+
+    /// Bind `name` in the global environment, replacing any old binding.
+    pub fn set_global<T: IntoRisp>(&mut self, name: &str, value: T) {
+        self.env.define_variable(name, &value.into_risp());
+    }
+
+    /// Call the lambda or builtin bound to `name` with already evaluated
+    /// arguments.
+    pub fn call_function(&mut self, name: &str, args: &[RispExp]) -> Result<RispExp, RispErr> {
+        match self.env.get(name) {
+            Some(f) => apply(&f, args, &mut self.env),
+            None => Err(RispErr::unbound(name, &self.env)),
+        }
+    }
+
+    /// See `RispEnv::register_fn`.
+    pub fn register_fn<Args, F>(&mut self, name: &str, func: F)
+    where
+        F: RispFn<Args> + 'static,
+    {
+        self.env.register_fn(name, func);
+    }
+
+    /// See `RispEnv::set_help`.
+    pub fn set_help(&mut self, name: &str, help: &str) {
+        self.env.set_help(name, help);
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for Interpreter {
+    fn drop(&mut self) {
+        // Lambdas refer back to the global scope, see `RispEnv::clear`
+        self.env.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn test_interpreter() {
+        let mut interp = Interpreter::new();
+        let output = interp.eval_str("(let x 2) (+ x 1)").expect("failed to eval");
+        assert_eq!(output, RispExp::Number(3.0));
+        assert_eq!(interp.get_global("x"), Some(RispExp::Number(2.0)));
+        assert_eq!(interp.get_global("nope"), None);
+
+        interp.set_global("greeting", "hi");
+        let output = interp.eval_str("(string-append greeting \"!\")").expect("failed to eval");
+        assert_eq!(output, RispExp::Str("hi!".to_string()));
+
+        interp.eval_str("(let add (fn (a b) (+ a b)))").expect("failed to eval");
+        let output = interp.call_function("add", &[RispExp::Number(1.0), RispExp::Number(2.0)]);
+        assert_eq!(output, Ok(RispExp::Number(3.0)));
+        let output = interp.call_function("string-upcase", &["a".into_risp()]);
+        assert_eq!(output, Ok(RispExp::Str("A".to_string())));
+        let output = interp.call_function("no-such-function", &[]);
+        assert_eq!(output, Err(RispErr::Unbound { name: "no-such-function".to_string(), suggestions: vec![] }));
+
+        interp.register_fn("double", |x: f64| x * 2.0);
+        let output = interp.eval_str("(double 4)").expect("failed to eval");
+        assert_eq!(output, RispExp::Number(8.0));
+        interp.set_help("double", "(double x) twice `x`");
+        assert_eq!(interp.env().help("double"), Some("(double x) twice `x`".to_string()));
+    }
+
+    #[test]
+    fn test_interpreter_drop() {
+        use std::rc::Rc;
+
+        let config = Rc::new(String::from("config"));
+        let mut interp = Interpreter::new();
+        let captured = config.clone();
+        interp.register_fn("config", move || captured.to_string());
+        interp.eval_str("(let f (fn (x) x))").expect("failed to eval");
+        assert_eq!(Rc::strong_count(&config), 2);
+
+        drop(interp);
+        assert_eq!(Rc::strong_count(&config), 1);
+    }
+
+    #[test]
+    fn test_interpreter_builtins() {
+        let interp = Interpreter::with_builtins(Builtins::core());
+        assert!(interp.get_global("+").is_some());
+        assert!(interp.get_global("sqrt").is_none());
+        assert!(interp.get_global("println").is_none());
+
+        let interp = Interpreter::with_builtins(Builtins { math: true, ..Builtins::core() });
+        assert!(interp.get_global("sqrt").is_some());
+        assert!(interp.get_global("println").is_none());
+    }
+
+    #[test]
+    fn test_interpreter_eval_file() {
+        let path = std::env::temp_dir().join("risp_test_interpreter_eval_file.risp");
+        std::fs::write(&path, "; a script\n(let y 4)\n(* y y)\n").expect("failed to write script");
+        let mut interp = Interpreter::new();
+        let output = interp.eval_file(&path);
+        std::fs::remove_file(&path).expect("failed to remove script");
+        assert_eq!(output, Ok(RispExp::Number(16.0)));
+
+        assert!(interp.eval_file("/no/such/file.risp").is_err());
+    }
+}
@@ -1,6 +1,13 @@
+use std::cmp::Ordering;
 use std::fmt;
+pub mod convert;
 pub mod env;
-pub use env::{RispEnv, RispFunc, standard_env};
+pub mod interpreter;
+pub mod pretty;
+pub use convert::{FromRisp, IntoRisp, IntoRispResult, RispFn};
+pub use env::{RispClosure, RispEnv, RispFunc, standard_env};
+pub use interpreter::{Builtins, Interpreter};
+pub use pretty::PrettyPrinter;
 
 #[cfg(feature = "comms-rs")]
 pub mod comms;
@@ -10,76 +17,880 @@ pub enum RispExp {
     Bool(bool),
     Symbol(String),
     Number(f64),
+    Str(String),
     List(Vec<RispExp>),
-    Lambda((Box<RispExp>, Box<RispExp>)),
+    Lambda(RispLambda),
+    Proc(RispProc),
 }
 
+impl RispExp {
+    /// The name of this value's type, as shown to users.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            RispExp::Bool(_) => "bool",
+            RispExp::Symbol(_) => "symbol",
+            RispExp::Number(_) => "number",
+            RispExp::Str(_) => "string",
+            RispExp::List(_) => "list",
+            RispExp::Lambda(_) => "lambda",
+            RispExp::Proc(_) => "procedure",
+        }
+    }
+
+    /// The number inside, without evaluating anything.
+    pub fn as_number(&self) -> Result<f64, RispErr> {
+        match self {
+            RispExp::Number(n) => Ok(*n),
+            other => Err(RispErr::type_error("a number", other)),
+        }
+    }
+
+    /// The elements of a list, without evaluating anything.
+    pub fn as_list(&self) -> Result<&[RispExp], RispErr> {
+        match self {
+            RispExp::List(v) => Ok(v),
+            other => Err(RispErr::type_error("a list", other)),
+        }
+    }
+
+    /// The string inside, without evaluating anything.
+    pub fn as_str(&self) -> Result<&str, RispErr> {
+        match self {
+            RispExp::Str(s) => Ok(s),
+            other => Err(RispErr::type_error("a string", other)),
+        }
+    }
+}
+
+/// A user defined function created by `fn`.
+///
+/// `closure` is the scope the lambda was defined in, so the body is evaluated
+/// lexically rather than in the caller's scope.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RispLambda {
+    pub params: Vec<String>,
+    pub body: Box<RispExp>,
+    pub closure: RispEnv,
+}
+
+impl PartialOrd for RispLambda {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        if self == other {
+            Some(Ordering::Equal)
+        } else {
+            None
+        }
+    }
+}
+
+/// A builtin procedure. Its arguments are evaluated before `func` is called,
+/// so it can be passed around and called like any lambda.
+#[derive(Clone)]
+pub struct RispProc {
+    pub name: String,
+    pub func: RispClosure,
+}
+
+impl fmt::Debug for RispProc {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RispProc")
+            .field("name", &self.name)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Builtins are compared by name, closures can't be compared.
+impl PartialEq for RispProc {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+impl PartialOrd for RispProc {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        if self == other {
+            Some(Ordering::Equal)
+        } else {
+            None
+        }
+    }
+}
+
+/// Prints values as risp source, so anything but a builtin can be read back
+/// with `parse`.
 impl fmt::Display for RispExp {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let str_rep = match self {
-            RispExp::Bool(b) => b.to_string(),
-            RispExp::Symbol(s) => s.clone(),
-            RispExp::Number(n) => n.to_string(),
+        match self {
+            RispExp::Bool(b) => write!(f, "{}", b),
+            RispExp::Symbol(s) => write!(f, "{}", s),
+            RispExp::Number(n) => write!(f, "{}", format_number(*n)),
+            // Debug formatting quotes and escapes the same way the reader
+            // unescapes
+            RispExp::Str(s) => write!(f, "{:?}", s),
             RispExp::List(v) => {
                 let xs: Vec<_> = v.iter().map(|x| x.to_string()).collect();
-                format!("({})", xs.join(","))
-            },
-            RispExp::Lambda((params, body)) => {
-                format!("{} {}", params, body)
+                write!(f, "({})", xs.join(" "))
             },
-        };
+            RispExp::Lambda(l) => write!(f, "(fn ({}) {})", l.params.join(" "), l.body),
+            RispExp::Proc(p) => write!(f, "#<builtin {}>", p.name),
+        }
+    }
+}
 
-        write!(f, "{}", str_rep)
+/// Shortest representation that reads back as the same number, switching to
+/// exponent notation for very large and very small magnitudes.
+pub(crate) fn format_number(n: f64) -> String {
+    let magnitude = n.abs();
+    if magnitude != 0.0 && magnitude.is_finite() && !(1e-5..1e16).contains(&magnitude) {
+        format!("{:e}", n)
+    } else {
+        n.to_string()
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+/// How many arguments a procedure accepts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Arity {
+    Exactly(usize),
+    AtLeast(usize),
+    Between(usize, usize),
+}
+
+impl Arity {
+    pub fn accepts(&self, n: usize) -> bool {
+        match *self {
+            Arity::Exactly(e) => n == e,
+            Arity::AtLeast(min) => n >= min,
+            Arity::Between(min, max) => n >= min && n <= max,
+        }
+    }
+
+    /// Error out unless `args` has an acceptable length for procedure `name`.
+    pub fn check(self, name: &str, args: &[RispExp]) -> Result<(), RispErr> {
+        if self.accepts(args.len()) {
+            Ok(())
+        } else {
+            Err(RispErr::Arity { name: name.to_string(), expected: self, found: args.len() })
+        }
+    }
+}
+
+impl fmt::Display for Arity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let plural = |n: usize| if n == 1 { "argument" } else { "arguments" };
+        match *self {
+            Arity::Exactly(n) => write!(f, "exactly {} {}", n, plural(n)),
+            Arity::AtLeast(n) => write!(f, "at least {} {}", n, plural(n)),
+            Arity::Between(min, max) => write!(f, "between {} and {} arguments", min, max),
+        }
+    }
+}
+
+#[derive(Debug)]
 pub enum RispErr {
     Reason(String),
+    /// `name` was called with `found` arguments.
+    Arity { name: String, expected: Arity, found: usize },
+    /// A value of the wrong type, `expected` names the type that was wanted.
+    Type { expected: &'static str, found: RispExp },
+    /// `name` isn't bound, `suggestions` are similarly spelled names that
+    /// are.
+    Unbound { name: String, suggestions: Vec<String> },
+    DivideByZero,
+    /// A reader error at a known location in the source.
+    Parse(String, Span),
+    /// Raised from risp code with `error`.
+    User(RispExp),
+    /// `err` was raised while evaluating `form`. The form and where it was
+    /// are kept so the error can be traced back to a `Span` through a
+    /// `SourceMap`. When `form` is a symbol, `parent` is the form it
+    /// appeared in.
+    InForm { err: Box<RispErr>, form: Box<RispExp>, parent: Option<Box<RispExp>>, location: Location },
+}
+
+impl RispErr {
+    pub fn type_error(expected: &'static str, found: &RispExp) -> RispErr {
+        RispErr::Type { expected, found: found.clone() }
+    }
+
+    /// `name` isn't bound in `env`, suggest names from `env` that look like
+    /// it.
+    pub fn unbound(name: &str, env: &RispEnv) -> RispErr {
+        RispErr::Unbound { name: name.to_string(), suggestions: env.suggest(name) }
+    }
+
+    /// Attach the form being evaluated, unless a more deeply nested form has
+    /// already been attached. A symbol also gets the first form around it.
+    pub fn in_form(self, form: &RispExp) -> RispErr {
+        match self {
+            RispErr::InForm { err, form: inner, parent, location } => {
+                let parent = match parent {
+                    None if matches!(*inner, RispExp::Symbol(_)) => Some(Box::new(form.clone())),
+                    parent => parent,
+                };
+                RispErr::InForm { err, form: inner, parent, location: location.within(form) }
+            },
+            _ => RispErr::InForm {
+                err: Box::new(self),
+                form: Box::new(form.clone()),
+                parent: None,
+                location: Location::at(form),
+            },
+        }
+    }
+
+    /// The underlying error, without any attached form.
+    pub fn root(&self) -> &RispErr {
+        match self {
+            RispErr::InForm { err, .. } => err.root(),
+            _ => self,
+        }
+    }
+
+    /// Where in the source this error happened, if it can be found in `maps`.
+    pub fn span(&self, maps: &[SourceMap]) -> Option<Span> {
+        match self {
+            RispErr::Parse(_, span) => Some(*span),
+            // Prefer where `SourceMap::eval` saw the error, only forms copied
+            // out of the source tree, e.g. lambda bodies, are matched by
+            // content. A lone symbol matches too much, look for its parent
+            // instead.
+            RispErr::InForm { err, form, parent, location } => err
+                .span(maps)
+                .or(location.span())
+                .or_else(|| {
+                    let form = parent.as_ref().unwrap_or(form);
+                    maps.iter().find_map(|m| m.find(form))
+                }),
+            _ => None,
+        }
+    }
+}
+
+/// Errors compare by their root cause, the attached form is only context.
+impl PartialEq for RispErr {
+    fn eq(&self, other: &Self) -> bool {
+        use RispErr::*;
+        match (self.root(), other.root()) {
+            (Reason(a), Reason(b)) => a == b,
+            (
+                Arity { name: a_name, expected: a_expected, found: a_found },
+                Arity { name: b_name, expected: b_expected, found: b_found },
+            ) => a_name == b_name && a_expected == b_expected && a_found == b_found,
+            (
+                Type { expected: a_expected, found: a_found },
+                Type { expected: b_expected, found: b_found },
+            ) => a_expected == b_expected && a_found == b_found,
+            (
+                Unbound { name: a_name, suggestions: a_suggestions },
+                Unbound { name: b_name, suggestions: b_suggestions },
+            ) => a_name == b_name && a_suggestions == b_suggestions,
+            (DivideByZero, DivideByZero) => true,
+            (Parse(a, a_span), Parse(b, b_span)) => a == b && a_span == b_span,
+            (User(a), User(b)) => a == b,
+            _ => false,
+        }
+    }
 }
 
 impl fmt::Display for RispErr {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             RispErr::Reason(s) => write!(f, "Error: {}", s),
+            RispErr::Arity { name, expected, found } => {
+                write!(f, "Error: `{}` takes {}, got {}", name, expected, found)
+            },
+            RispErr::Type { expected, found } => {
+                write!(f, "Error: expected {}, found `{}`", expected, found)
+            },
+            RispErr::Unbound { name, suggestions } => {
+                write!(f, "Error: unbound symbol `{}`", name)?;
+                let quoted: Vec<_> = suggestions.iter().map(|s| format!("`{}`", s)).collect();
+                match quoted.split_last() {
+                    None => Ok(()),
+                    Some((last, [])) => write!(f, ", did you mean {}?", last),
+                    Some((last, rest)) => write!(f, ", did you mean {} or {}?", rest.join(", "), last),
+                }
+            },
+            RispErr::DivideByZero => write!(f, "Error: division by zero"),
+            RispErr::Parse(s, span) => write!(f, "Error: {} at {}:{}", s, span.line, span.col),
+            RispErr::User(exp) => write!(f, "Error: {}", exp),
+            RispErr::InForm { err, .. } => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for RispErr {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RispErr::InForm { err, .. } => Some(err.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+/// Format `err` as `file:line:col: message` followed by the offending source
+/// line with the span underlined. Falls back to `file: message` when the
+/// error can't be located in `maps`.
+pub fn render_error(err: &RispErr, file: &str, source: &str, maps: &[SourceMap]) -> String {
+    let span = match err.span(maps) {
+        Some(span) => span,
+        None => return format!("{}: {}", file, err),
+    };
+
+    // The location is already in the prefix
+    let message = match err.root() {
+        RispErr::Parse(s, _) => format!("Error: {}", s),
+        root => root.to_string(),
+    };
+    let line = source.lines().nth(span.line - 1).unwrap_or("");
+    let gutter = span.line.to_string();
+    // Keep tabs so the caret lines up with the excerpt
+    let indent: String = line
+        .chars()
+        .take(span.col - 1)
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    let width = source
+        .get(span.offset..span.offset + span.len)
+        .unwrap_or("")
+        .chars()
+        .take_while(|&c| c != '\n')
+        .count()
+        .max(1);
+
+    format!(
+        "{}:{}:{}: {}\n{} | {}\n{} | {}{}",
+        file, span.line, span.col, message,
+        gutter, line,
+        " ".repeat(gutter.len()), indent, "^".repeat(width),
+    )
+}
+
+/// Location of a token or expression in the source text. `offset` and `len`
+/// are in bytes, `line` and `col` are 1-based with `col` counted in chars.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Span {
+    pub offset: usize,
+    pub len: usize,
+    pub line: usize,
+    pub col: usize,
+}
+
+impl Span {
+    /// The span covering everything from the start of `self` to the end of
+    /// `end`.
+    pub fn to(self, end: Span) -> Span {
+        Span {
+            len: end.offset + end.len - self.offset,
+            ..self
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Token {
+    pub text: String,
+    pub span: Span,
+}
+
+impl PartialEq<&str> for Token {
+    fn eq(&self, other: &&str) -> bool {
+        self.text == *other
+    }
+}
+
+/// Spans of every node in a parsed expression, recorded by the reader in
+/// pre-order. They're kept beside the tree rather than in it so expressions
+/// compare and print the same whether or not they came from source.
+#[derive(Clone, Debug)]
+pub struct SourceMap {
+    root: RispExp,
+    spans: Vec<Span>,
+}
+
+impl SourceMap {
+    pub fn root(&self) -> &RispExp {
+        &self.root
+    }
+
+    pub fn root_span(&self) -> Span {
+        self.spans[0]
+    }
+
+    /// The span of the first node in the tree equal to `form`.
+    pub fn find(&self, form: &RispExp) -> Option<Span> {
+        preorder_index(&self.root, &mut |node| node == form).and_then(|i| self.spans.get(i).copied())
+    }
+
+    /// Evaluate the mapped expression. An error records the span of the
+    /// form that raised it, so `RispErr::span` finds it even when the same
+    /// form appears more than once.
+    pub fn eval(&self, env: &mut RispEnv) -> Result<RispExp, RispErr> {
+        eval_ref(&self.root, env).map_err(|err| match err {
+            RispErr::InForm { err, form, parent, location } => {
+                let location = location.resolve(&self.root, &self.spans);
+                RispErr::InForm { err, form, parent, location }
+            },
+            err => err,
+        })
+    }
+}
+
+/// The pre-order index of the first node in `tree` matching `pred`, which
+/// is also its index in a `SourceMap`.
+fn preorder_index(tree: &RispExp, pred: &mut impl FnMut(&RispExp) -> bool) -> Option<usize> {
+    fn walk(node: &RispExp, pred: &mut impl FnMut(&RispExp) -> bool, index: &mut usize) -> Option<usize> {
+        if pred(node) {
+            return Some(*index);
+        }
+        *index += 1;
+        if let RispExp::List(v) = node {
+            for child in v {
+                if let Some(found) = walk(child, pred, index) {
+                    return Some(found);
+                }
+            }
+        }
+        None
+    }
+
+    let mut index = 0;
+    walk(tree, pred, &mut index)
+}
+
+/// Where in the source an error happened, see `RispErr::InForm`.
+#[derive(Clone, Copy, Debug)]
+pub struct Location(Position);
+
+#[derive(Clone, Copy, Debug)]
+enum Position {
+    /// Node `index`, in pre-order, of the form being evaluated at `root`.
+    /// Only compared against forms that are alive at the same time, while
+    /// the error makes its way out of the evaluation.
+    Node { root: usize, index: usize },
+    /// Resolved by `SourceMap::eval`.
+    Span(Span),
+    /// The form wasn't part of the mapped tree, e.g. it was in a lambda
+    /// body.
+    Unknown,
+}
+
+/// Identifies a node by where it lives, which is only meaningful while it
+/// does.
+fn node_address(exp: &RispExp) -> usize {
+    exp as *const RispExp as usize
+}
+
+impl Location {
+    fn at(form: &RispExp) -> Self {
+        Location(Position::Node { root: node_address(form), index: 0 })
+    }
+
+    /// Re-root the position on `form`, which encloses the form that failed
+    /// unless it was copied out of the tree.
+    fn within(self, form: &RispExp) -> Self {
+        match self.0 {
+            Position::Node { root, index } if root != node_address(form) => {
+                match preorder_index(form, &mut |node| node_address(node) == root) {
+                    Some(offset) => Location(Position::Node { root: node_address(form), index: offset + index }),
+                    None => self,
+                }
+            },
+            _ => self,
+        }
+    }
+
+    /// Turn the position into a span, once evaluating `root` has returned.
+    fn resolve(self, root: &RispExp, spans: &[Span]) -> Self {
+        match self.within(root).0 {
+            Position::Node { root: node, index } if node == node_address(root) => match spans.get(index) {
+                Some(span) => Location(Position::Span(*span)),
+                None => Location(Position::Unknown),
+            },
+            Position::Node { .. } => Location(Position::Unknown),
+            _ => self,
+        }
+    }
+
+    fn span(&self) -> Option<Span> {
+        match self.0 {
+            Position::Span(span) => Some(span),
+            _ => None,
+        }
+    }
+}
+
+/// Walks the source text one char at a time, keeping track of line and
+/// column.
+struct Scanner<'s> {
+    chars: std::iter::Peekable<std::str::CharIndices<'s>>,
+    len: usize,
+    line: usize,
+    col: usize,
+}
+
+impl<'s> Scanner<'s> {
+    fn new(source: &'s str) -> Self {
+        Self {
+            chars: source.char_indices().peekable(),
+            len: source.len(),
+            line: 1,
+            col: 1,
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().map(|&(_, c)| c)
+    }
+
+    /// The char after the one `peek` returns.
+    fn peek_second(&self) -> Option<char> {
+        self.chars.clone().nth(1).map(|(_, c)| c)
+    }
+
+    /// Skip a `#| ... |#` block comment, starting at the opening `#|`.
+    /// Block comments nest. Returns `false` if the input ends first.
+    fn skip_block_comment(&mut self) -> bool {
+        let mut depth = 0;
+        while let Some(c) = self.bump() {
+            if c == '#' && self.peek() == Some('|') {
+                self.bump();
+                depth += 1;
+            } else if c == '|' && self.peek() == Some('#') {
+                self.bump();
+                depth -= 1;
+                if depth == 0 {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    /// An empty span at the current position.
+    fn here(&mut self) -> Span {
+        let offset = self.chars.peek().map(|&(i, _)| i).unwrap_or(self.len);
+        Span { offset, len: 0, line: self.line, col: self.col }
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let (_, c) = self.chars.next()?;
+        if c == '\n' {
+            self.line += 1;
+            self.col = 1;
+        } else {
+            self.col += 1;
+        }
+        Some(c)
+    }
+}
+
+/// Split `expr` into tokens, also returning the (empty) span just past the
+/// end of the input.
+fn lex(expr: &str) -> (Vec<Token>, Span) {
+    let mut scanner = Scanner::new(expr);
+    let mut tokens = vec![];
+
+    // Skip a `#!` interpreter line so scripts can be made executable
+    if expr.starts_with("#!") {
+        while !matches!(scanner.bump(), Some('\n') | None) {}
+    }
+
+    while let Some(c) = scanner.peek() {
+        let start = scanner.here();
+        let mut text = String::new();
+        if c.is_whitespace() {
+            scanner.bump();
+            continue;
+        } else if c == ';' {
+            // Line comment
+            while !matches!(scanner.bump(), Some('\n') | None) {}
+            continue;
+        } else if c == '#' && scanner.peek_second() == Some('|') {
+            if scanner.skip_block_comment() {
+                continue;
+            }
+            // Hand the opener to the reader so it can report it
+            text.push_str("#|");
+        } else if c == '#' && scanner.peek_second() == Some(';') {
+            // Datum comment, the reader skips the expression that follows
+            text.push_str("#;");
+            scanner.bump();
+            scanner.bump();
+        } else if c == '(' || c == ')' || c == '\'' || c == '`' {
+            text.push(c);
+            scanner.bump();
+        } else if c == ',' {
+            // `,` or `,@`
+            text.push(c);
+            scanner.bump();
+            if scanner.peek() == Some('@') {
+                text.push('@');
+                scanner.bump();
+            }
+        } else if c == '"' {
+            // String literals run to the closing quote. Escapes are kept as
+            // written and handled by the reader.
+            text.push(c);
+            scanner.bump();
+            while let Some(c) = scanner.bump() {
+                text.push(c);
+                if c == '\\' {
+                    if let Some(escaped) = scanner.bump() {
+                        text.push(escaped);
+                    }
+                } else if c == '"' {
+                    break;
+                }
+            }
+        } else {
+            while let Some(c) = scanner.peek() {
+                if c.is_whitespace() || c == '(' || c == ')' || c == '"' || c == ';' {
+                    break;
+                }
+                text.push(c);
+                scanner.bump();
+            }
         }
+        let span = Span { len: text.len(), ..start };
+        tokens.push(Token { text, span });
     }
+
+    (tokens, scanner.here())
 }
 
-pub fn tokenize(expr: &str) -> Vec<String> {
-    expr.replace('(', " ( ")
-        .replace(')', " ) ")
-        .split_whitespace()
-        .map(|x| x.to_string())
-        .collect()
+pub fn tokenize(expr: &str) -> Vec<Token> {
+    lex(expr).0
 }
 
+/// Parse a single expression. Anything other than comments after it is an
+/// error, use `parse_all` for programs with several top-level forms.
 pub fn parse(program: &str) -> Result<RispExp, RispErr> {
-    read_from_tokens(&tokenize(program))
+    parse_spanned(program).map(|(exp, _)| exp)
 }
 
-pub fn token_count(re: &RispExp) -> usize {
-    match re {
-        RispExp::List(v) => 2 + v.iter().map(token_count).sum::<usize>(),
-        _ => 1,
+/// Like `parse`, but also returns the spans of every node in the expression.
+pub fn parse_spanned(program: &str) -> Result<(RispExp, SourceMap), RispErr> {
+    let (tokens, eof) = lex(program);
+    let mut reader = Reader::new(&tokens, eof);
+    let exp = reader.read_exp()?;
+    reader.skip_datum_comments()?;
+    if let Some(token) = reader.tokens.get(reader.pos) {
+        let msg = if token == &")" {
+            "unexpected `)`"
+        } else {
+            "unexpected input after the expression"
+        };
+        return Err(RispErr::Parse(msg.to_string(), token.span));
     }
+    let map = SourceMap { root: exp.clone(), spans: reader.spans };
+    Ok((exp, map))
+}
+
+/// Parse every top-level expression in `program`.
+pub fn parse_all(program: &str) -> Result<Vec<RispExp>, RispErr> {
+    Ok(parse_all_spanned(program)?.into_iter().map(|(exp, _)| exp).collect())
 }
 
-pub fn read_from_tokens(tokens: &[String]) -> Result<RispExp, RispErr> {
-    let (token, mut rest) = tokens.split_first().expect("failed to pop from tokens");
-    match token.as_str() {
-        "(" => {
-            let mut list = vec![];
-            while rest[0] != ")" {
-                let next_token = read_from_tokens(rest).expect("failed to read from tokens");
-                let tlen = token_count(&next_token);
-                list.push(next_token);
-                (_, rest) = rest.split_at(tlen);
+/// Like `parse_all`, but also returns the spans of every node in each
+/// expression.
+pub fn parse_all_spanned(program: &str) -> Result<Vec<(RispExp, SourceMap)>, RispErr> {
+    let (tokens, eof) = lex(program);
+    let mut reader = Reader::new(&tokens, eof);
+    let mut forms = vec![];
+    loop {
+        reader.skip_datum_comments()?;
+        if reader.pos >= reader.tokens.len() {
+            break;
+        }
+        let exp = reader.read_exp()?;
+        let spans = std::mem::take(&mut reader.spans);
+        forms.push((exp.clone(), SourceMap { root: exp, spans }));
+    }
+    Ok(forms)
+}
+
+/// Whether `program` stops partway through an expression: an unclosed `(`,
+/// string literal or block comment, or a `#;` with nothing after it. The REPL
+/// uses this to decide whether to keep reading lines.
+pub fn is_incomplete(program: &str) -> bool {
+    let tokens = tokenize(program);
+    let mut depth = 0;
+    for token in tokens.iter() {
+        if token == &"(" {
+            depth += 1;
+        } else if token == &")" {
+            depth -= 1;
+        }
+    }
+    if depth > 0 {
+        return true;
+    }
+
+    // Unterminated strings and block comments run to the end of the input,
+    // so only the last token can be one
+    match tokens.last() {
+        Some(token) if ["#|", "#;", "'", "`", ",", ",@"].iter().any(|t| token == t) => true,
+        Some(token) if token.text.starts_with('"') => {
+            let mut chars = token.text.chars().skip(1);
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => {
+                        chars.next();
+                    },
+                    '"' => return false,
+                    _ => {},
+                }
             }
-            Ok(RispExp::List(list))
+            true
         },
-        ")" => Err(RispErr::Reason("unexpected `)`".to_string())),
-        _ => Ok(parse_atom(token)),
+        _ => false,
+    }
+}
+
+/// Evaluate every top-level expression in `program` in order, returning the
+/// value of the last one. An empty program evaluates to the empty list.
+pub fn run_program(program: &str, env: &mut RispEnv) -> Result<RispExp, RispErr> {
+    let mut result = RispExp::List(vec![]);
+    for exp in parse_all(program)? {
+        result = eval(exp, env)?;
+    }
+    Ok(result)
+}
+
+pub fn read_from_tokens(tokens: &[Token]) -> Result<RispExp, RispErr> {
+    // Without the source text, EOF is reported just after the last token
+    let eof = match tokens.last() {
+        Some(last) => Span {
+            offset: last.span.offset + last.span.len,
+            len: 0,
+            line: last.span.line,
+            col: last.span.col + last.text.chars().count(),
+        },
+        None => Span { offset: 0, len: 0, line: 1, col: 1 },
+    };
+    Reader::new(tokens, eof).read_exp()
+}
+
+struct Reader<'t> {
+    tokens: &'t [Token],
+    pos: usize,
+    eof: Span,
+    spans: Vec<Span>,
+}
+
+impl<'t> Reader<'t> {
+    fn new(tokens: &'t [Token], eof: Span) -> Self {
+        Self { tokens, pos: 0, eof, spans: vec![] }
+    }
+
+    /// Skip any `#;` datum comments at `tokens[pos]` along with the
+    /// expression each one comments out.
+    fn skip_datum_comments(&mut self) -> Result<(), RispErr> {
+        while let Some(token) = self.tokens.get(self.pos) {
+            if token != &"#;" {
+                break;
+            }
+            self.pos += 1;
+            let spans = self.spans.len();
+            self.read_exp()?;
+            // The skipped expression isn't part of the tree
+            self.spans.truncate(spans);
+        }
+        Ok(())
+    }
+
+    /// Read a single expression starting at `tokens[pos]`, leaving `pos` just
+    /// past the last token consumed.
+    fn read_exp(&mut self) -> Result<RispExp, RispErr> {
+        self.skip_datum_comments()?;
+        let token = match self.tokens.get(self.pos) {
+            Some(token) => token,
+            None => return Err(RispErr::Parse("unexpected EOF".to_string(), self.eof)),
+        };
+        self.pos += 1;
+
+        // Reserve our slot now so spans stay in pre-order
+        let slot = self.spans.len();
+        self.spans.push(token.span);
+
+        match token.text.as_str() {
+            "(" => {
+                let mut list = vec![];
+                loop {
+                    self.skip_datum_comments()?;
+                    match self.tokens.get(self.pos) {
+                        Some(t) if t == &")" => {
+                            self.pos += 1;
+                            self.spans[slot] = token.span.to(t.span);
+                            return Ok(RispExp::List(list));
+                        },
+                        Some(_) => list.push(self.read_exp()?),
+                        None => {
+                            return Err(RispErr::Parse(
+                                "unbalanced parentheses: `(` is never closed".to_string(), token.span
+                            ));
+                        },
+                    }
+                }
+            },
+            "'" | "`" | "," | ",@" => {
+                // Shorthand, `'x` reads as `(quote x)` and so on
+                let name = match token.text.as_str() {
+                    "'" => "quote",
+                    "`" => "quasiquote",
+                    "," => "unquote",
+                    _ => "unquote-splicing",
+                };
+                self.spans.push(token.span);
+                let datum = self.read_exp()?;
+                self.spans[slot] = token.span.to(self.spans[slot + 2]);
+                Ok(RispExp::List(vec![RispExp::Symbol(name.to_string()), datum]))
+            },
+            ")" => Err(RispErr::Parse("unexpected `)`".to_string(), token.span)),
+            "#|" => Err(RispErr::Parse("unterminated block comment".to_string(), token.span)),
+            text if text.starts_with('"') => parse_string(text, token.span),
+            _ => Ok(parse_atom(&token.text)),
+        }
+    }
+}
+
+/// Turn a quoted string literal token into a `RispExp::Str`, processing
+/// escape sequences.
+fn parse_string(token: &str, span: Span) -> Result<RispExp, RispErr> {
+    let unterminated = || RispErr::Parse("unterminated string literal".to_string(), span);
+    let mut chars = token.chars().skip(1);
+    let mut out = String::new();
+    loop {
+        match chars.next().ok_or_else(unterminated)? {
+            '"' => return Ok(RispExp::Str(out)),
+            '\\' => {
+                let escaped = match chars.next().ok_or_else(unterminated)? {
+                    'n' => '\n',
+                    't' => '\t',
+                    'r' => '\r',
+                    '0' => '\0',
+                    '"' => '"',
+                    '\\' => '\\',
+                    'u' => {
+                        // \u{XXXX}
+                        let invalid = || RispErr::Parse("invalid unicode escape".to_string(), span);
+                        if chars.next() != Some('{') {
+                            return Err(invalid());
+                        }
+                        let hex: String = chars.by_ref().take_while(|&c| c != '}').collect();
+                        u32::from_str_radix(&hex, 16)
+                            .ok()
+                            .and_then(char::from_u32)
+                            .ok_or_else(invalid)?
+                    },
+                    c => return Err(RispErr::Parse(format!("unknown escape `\\{}`", c), span)),
+                };
+                out.push(escaped);
+            },
+            c => out.push(c),
+        }
     }
 }
 
@@ -98,6 +909,13 @@ pub fn parse_atom(token: &str) -> RispExp {
 }
 
 pub fn eval(x: RispExp, env: &mut RispEnv) -> Result<RispExp, RispErr> {
+    eval_ref(&x, env)
+}
+
+/// Like `eval`, but borrows `x`. Errors from a borrowed tree, e.g. the root
+/// of a `SourceMap`, can be located exactly even when the same form appears
+/// more than once.
+pub fn eval_ref(x: &RispExp, env: &mut RispEnv) -> Result<RispExp, RispErr> {
     //println!("eval() x: {:?}", x);
     match x {
         RispExp::Bool(_b) => Ok(x.clone()),
@@ -105,80 +923,87 @@ pub fn eval(x: RispExp, env: &mut RispEnv) -> Result<RispExp, RispErr> {
             // Variable lookup
             if let Some(exp) = env.get(s.as_str()) {
                 Ok(exp)
+            } else if env.lenient_symbols() {
+                // Compatibility with scripts that relied on unbound symbols
+                // evaluating to themselves
+                Ok(x.clone())
             } else {
-                Ok(RispExp::Symbol(s))
+                // Point at the symbol itself rather than the enclosing form
+                Err(RispErr::unbound(s, env).in_form(x))
             }
         },
         RispExp::Number(_n) => {
             // Numbers are already evaluated as far as we wish them to be
-            Ok(x)
+            Ok(x.clone())
         },
+        RispExp::Str(_) => Ok(x.clone()),
         RispExp::List(v) => {
-            // Lists are special. Procedure calls, defines, flow control
-            let (first, rest) = v[..].split_first().expect("failed to split list");
-            match first {
-                RispExp::Symbol(p) => {
-                    // Handle procedures
-                    if let Some(f) = env.get_function(p) {
-                        f(rest, env)
-                    } else {
-                        // Handle lambdas
-                        if let Some(l) = env.get(p) {
-                            match l {
-                                RispExp::Lambda((params, body)) => {
-                                    let params = if let RispExp::List(pars) = *params {
-                                        pars
-                                    } else {
-                                        return Err(RispErr::Reason("lambda parameters must be a RispExp::List".to_string()));
-                                    };
-
-                                    if rest.len() != params.len() {
-                                        return Err(RispErr::Reason(
-                                            "length of passed args doesn't match expected parameters".to_string()
-                                            ));
-                                    }
-
-                                    // If we got here it seems things parsed correctly
-
-                                    // Create our inner scope, add parameters to it
-                                    let mut inner_scope = RispEnv::new();
-                                    for (sym, arg) in params.iter().zip(rest.iter()) {
-                                        if let RispExp::Symbol(s) = sym {
-                                            inner_scope.define_variable(s, arg)
-                                        } else {
-                                            return Err(RispErr::Reason("parameter RispExp didn't evaluate to symbol".to_string()));
-                                        }
-                                    }
-                                    inner_scope.outer = Some(env);
-
-                                    eval(*body, &mut inner_scope)
-                                },
-                                _ => Err(RispErr::Reason(format!("failed to find function or lambda {:?}", first))),
-                            }
-                        } else {
-                            Err(RispErr::Reason(format!("failed to find function or lambda {:?}", first)))
-                        }
-                    }
-                },
-                _ => {
-                    Err(RispErr::Reason(format!("{:?} not implemented", first)))
-                },
-            }
+            // Remember which form failed so the error can be located later
+            eval_list(v, env).map_err(|e| e.in_form(x))
+        },
+        RispExp::Lambda(_) | RispExp::Proc(_) => {
+            // Procedures are values, they evaluate to themselves
+            Ok(x.clone())
         },
-        RispExp::Lambda(_) => Err(RispErr::Reason("Unexpected form".to_string())),
     }
 }
 
-pub fn eval_to_number(x: &RispExp, env: &mut RispEnv) -> Result<f64, RispErr> {
-    match eval(x.clone(), env) {
-        Ok(re) => {
-            match re {
-                RispExp::Number(n) => Ok(n),
-                _ => Err(RispErr::Reason(format!("{:?} did not eval to a number", x))),
+fn eval_list(v: &[RispExp], env: &mut RispEnv) -> Result<RispExp, RispErr> {
+    // Lists are special. Procedure calls, defines, flow control
+    let (first, rest) = match v.split_first() {
+        Some(split) => split,
+        // `()` is nil, the empty list, and evaluates to itself
+        None => return Ok(RispExp::List(vec![])),
+    };
+    let callee = match first {
+        RispExp::Symbol(p) => {
+            // Special forms get their arguments unevaluated
+            if let Some(f) = env.get_special_form(p) {
+                return f(rest, env);
+            }
+            match env.get(p) {
+                Some(exp) => exp,
+                None => return Err(RispErr::unbound(p, env)),
             }
         },
-        Err(rerr) => Err(rerr),
+        // Anything else in operator position, e.g. `((fn (x) x) 1)`, is
+        // evaluated and whatever it yields gets called
+        _ => eval_ref(first, env)?,
+    };
+
+    // Applicative order: arguments are evaluated in the caller's scope
+    // before the call.
+    let mut args = vec![];
+    for arg in rest {
+        args.push(eval_ref(arg, env)?);
     }
+    apply(&callee, &args, env)
+}
+
+/// Call a builtin or a lambda with already evaluated arguments.
+pub fn apply(f: &RispExp, args: &[RispExp], env: &mut RispEnv) -> Result<RispExp, RispErr> {
+    match f {
+        RispExp::Proc(p) => (p.func)(args, env),
+        RispExp::Lambda(l) => apply_lambda(l, args),
+        other => Err(RispErr::type_error("a procedure", other)),
+    }
+}
+
+pub fn apply_lambda(lambda: &RispLambda, args: &[RispExp]) -> Result<RispExp, RispErr> {
+    Arity::Exactly(lambda.params.len()).check("lambda", args)?;
+
+    // Create our inner scope on top of the one the lambda was defined in,
+    // add parameters to it
+    let mut inner_scope = RispEnv::with_outer(&lambda.closure);
+    for (sym, arg) in lambda.params.iter().zip(args.iter()) {
+        inner_scope.define_variable(sym, arg);
+    }
+
+    eval_ref(&lambda.body, &mut inner_scope)
+}
+
+pub fn eval_to_number(x: &RispExp, env: &mut RispEnv) -> Result<f64, RispErr> {
+    eval_ref(x, env)?.as_number()
 }
 
 #[cfg(test)]
@@ -195,6 +1020,35 @@ mod tests {
             vec!["(", "begin", "(", "define", "r", "10", ")", "(", "*", "pi",
                  "(", "*", "r", "r", ")", ")", ")"
             ]);
+
+        let expr = "'(a `b ,c ,@d)";
+        assert_eq!(tokenize(expr), vec!["'", "(", "a", "`", "b", ",", "c", ",@", "d", ")"]);
+    }
+
+    #[test]
+    fn test_quote_shorthand() {
+        let quote = |name: &str, exp: RispExp| RispExp::List(vec![RispExp::Symbol(name.to_string()), exp]);
+        assert_eq!(parse("'x"), Ok(quote("quote", RispExp::Symbol("x".to_string()))));
+        assert_eq!(parse("'(1 2)"), parse("(quote (1 2))"));
+        assert_eq!(parse("`(a ,b ,@c)"), parse("(quasiquote (a (unquote b) (unquote-splicing c)))"));
+        assert_eq!(parse("''x"), parse("(quote (quote x))"));
+        assert_eq!(parse("'#;skipped kept"), parse("(quote kept)"));
+        assert_eq!(parse("'"), Err(RispErr::Parse(
+            "unexpected EOF".to_string(),
+            Span { offset: 1, len: 0, line: 1, col: 2 },
+        )));
+        assert!(is_incomplete("(f '"));
+        assert!(is_incomplete("'"));
+
+        // The shorthand covers its datum in the source map
+        let (exp, map) = parse_spanned(" '(a b)").expect("failed to parse");
+        assert_eq!(map.root_span(), Span { offset: 1, len: 6, line: 1, col: 2 });
+        if let RispExp::List(v) = &exp {
+            assert_eq!(map.find(&v[0]), Some(Span { offset: 1, len: 1, line: 1, col: 2 }));
+            assert_eq!(map.find(&v[1]), Some(Span { offset: 2, len: 5, line: 1, col: 3 }));
+        } else {
+            panic!("expected a list, got {}", exp);
+        }
     }
 
     #[test]
@@ -225,4 +1079,374 @@ mod tests {
         ]);
         assert_eq!(output, truth);
     }
+
+    #[test]
+    fn test_parse_errors() {
+        let expr = "(+ 1 2";
+        assert_eq!(parse(expr), Err(RispErr::Parse(
+            "unbalanced parentheses: `(` is never closed".to_string(),
+            Span { offset: 0, len: 1, line: 1, col: 1 },
+        )));
+
+        let expr = "(+ 1\n   (* 2 3)";
+        assert_eq!(parse(expr), Err(RispErr::Parse(
+            "unbalanced parentheses: `(` is never closed".to_string(),
+            Span { offset: 0, len: 1, line: 1, col: 1 },
+        )));
+
+        let expr = "";
+        assert_eq!(parse(expr), Err(RispErr::Parse(
+            "unexpected EOF".to_string(),
+            Span { offset: 0, len: 0, line: 1, col: 1 },
+        )));
+
+        let expr = ")";
+        assert_eq!(parse(expr), Err(RispErr::Parse(
+            "unexpected `)`".to_string(),
+            Span { offset: 0, len: 1, line: 1, col: 1 },
+        )));
+
+        let expr = "(+ 1 2)\n )";
+        assert_eq!(parse(expr), Err(RispErr::Parse(
+            "unexpected `)`".to_string(),
+            Span { offset: 9, len: 1, line: 2, col: 2 },
+        )));
+    }
+
+    #[test]
+    fn test_malformed_never_panics() {
+        let inputs = [
+            "", " ", "\n\t", "(", ")", "((", "))", ")(", "(()", "())", "(((((", ")))))",
+            "(+ 1 2", "(+ 1 2))", "(let a", "(fn (x) (+ x 1)", ") 1 2", "(1 (2 (3 (4",
+        ];
+        for input in inputs.iter() {
+            let _ = parse(input);
+            let _ = read_from_tokens(&tokenize(input));
+        }
+
+        for input in ["", "(", ")", "(+ 1 2", "(()", ")(", "(+ 1 2))"].iter() {
+            assert!(parse(input).is_err(), "{:?} should not parse", input);
+        }
+    }
+
+    #[test]
+    fn test_token_spans() {
+        let tokens = tokenize("(let a\n  (+ 10 5))");
+        let spans: Vec<_> = tokens.iter().map(|t| (t.span.offset, t.span.line, t.span.col)).collect();
+        assert_eq!(spans, vec![
+            (0, 1, 1), (1, 1, 2), (5, 1, 6),
+            (9, 2, 3), (10, 2, 4), (12, 2, 6), (15, 2, 9), (16, 2, 10), (17, 2, 11),
+        ]);
+        assert_eq!(tokens[5].span.len, 2);
+    }
+
+    #[test]
+    fn test_source_map() {
+        let (exp, map) = parse_spanned("(+ 1\n   (* 2 3))").expect("failed to parse");
+        assert_eq!(map.root(), &exp);
+        assert_eq!(map.root_span(), Span { offset: 0, len: 16, line: 1, col: 1 });
+
+        let inner = parse("(* 2 3)").expect("failed to parse");
+        assert_eq!(map.find(&inner), Some(Span { offset: 8, len: 7, line: 2, col: 4 }));
+        assert_eq!(map.find(&RispExp::Number(3.0)), Some(Span { offset: 13, len: 1, line: 2, col: 9 }));
+        assert_eq!(map.find(&RispExp::Number(4.0)), None);
+    }
+
+    #[test]
+    fn test_render_error() {
+        let source = "(let a 1)\n(let b\n   (undefined-fn a))";
+        let mut forms = parse_all_spanned(source).expect("failed to parse");
+        let (_, map) = forms.pop().expect("missing form");
+
+        let mut env = standard_env();
+        let err = map.eval(&mut env).expect_err("should fail");
+        assert_eq!(render_error(&err, "script.risp", source, std::slice::from_ref(&map)),
+            "script.risp:3:4: Error: unbound symbol `undefined-fn`\n\
+             3 |    (undefined-fn a))\n\
+            \u{20} |    ^^^^^^^^^^^^^^^^");
+
+        // Spans running past the end of the line are cut at the newline
+        let err = RispErr::Reason("oops".to_string()).in_form(map.root());
+        assert_eq!(render_error(&err, "script.risp", source, &[map]),
+            "script.risp:2:1: Error: oops\n2 | (let b\n  | ^^^^^^");
+
+        let err = parse_spanned("(+ 1 2))").expect_err("should fail");
+        assert_eq!(render_error(&err, "<repl>", "(+ 1 2))", &[]),
+            "<repl>:1:8: Error: unexpected `)`\n1 | (+ 1 2))\n  |        ^");
+
+        let err = RispErr::Reason("no location".to_string());
+        assert_eq!(render_error(&err, "<repl>", "", &[]), "<repl>: Error: no location");
+    }
+
+    #[test]
+    fn test_render_error_repeated_form() {
+        // The failing form is located where it was evaluated, not at the
+        // first identical form
+        let source = "(let x 1)\n(+ x 1)\n(let x \"s\")\n(+ x 1)";
+        let forms = parse_all_spanned(source).expect("failed to parse");
+        let maps: Vec<_> = forms.into_iter().map(|(_, map)| map).collect();
+
+        let mut env = standard_env();
+        let mut err = None;
+        for map in &maps {
+            if let Err(e) = map.eval(&mut env) {
+                err = Some(e);
+                break;
+            }
+        }
+        let err = err.expect("should fail");
+        assert_eq!(render_error(&err, "script.risp", source, &maps),
+            "script.risp:4:1: Error: expected a number, found `\"s\"`\n\
+             4 | (+ x 1)\n\
+            \u{20} | ^^^^^^^");
+
+        // Also within a form, through special forms
+        let source = "(let x \"s\")\n(if false (+ x 1) (+ x 1))";
+        let forms = parse_all_spanned(source).expect("failed to parse");
+        let maps: Vec<_> = forms.into_iter().map(|(_, map)| map).collect();
+        let err = maps.iter().find_map(|map| map.eval(&mut env).err()).expect("should fail");
+        assert_eq!(err.span(&maps), Some(Span { offset: 30, len: 7, line: 2, col: 19 }));
+    }
+
+    #[test]
+    fn test_render_error_unbound_symbol() {
+        fn render(source: &str) -> String {
+            let forms = parse_all_spanned(source).expect("failed to parse");
+            let maps: Vec<_> = forms.into_iter().map(|(_, map)| map).collect();
+            let mut env = standard_env();
+            let err = maps
+                .iter()
+                .find_map(|map| map.eval(&mut env).err())
+                .expect("should fail");
+            render_error(&err, "script.risp", source, &maps)
+        }
+
+        // The symbol also appears earlier, as data
+        let rendered = render("(let tag (quote total))\n(list tag)\n(+ total 1)");
+        assert!(rendered.starts_with("script.risp:3:4: Error: unbound symbol `total`"), "{}", rendered);
+
+        // Lambda bodies are copies, the symbol is found through its call
+        let rendered = render("(let tag (quote total))\n(let f (fn () (+ total 1)))\n(f)");
+        assert!(rendered.starts_with("script.risp:2:15: Error: unbound symbol `total`"), "{}", rendered);
+    }
+
+    #[test]
+    fn test_string_literals() {
+        let expr = r#"(concat "hello world" "(not a list)")"#;
+        assert_eq!(tokenize(expr), vec!["(", "concat", r#""hello world""#, r#""(not a list)""#, ")"]);
+
+        let expr = r#""tab\there\n\"quoted\" \\ \u{1F600}""#;
+        assert_eq!(parse(expr), Ok(RispExp::Str("tab\there\n\"quoted\" \\ \u{1F600}".to_string())));
+
+        let expr = r#"(f"a"b)"#;
+        assert_eq!(parse(expr), Ok(RispExp::List(vec![
+            RispExp::Symbol("f".to_string()),
+            RispExp::Str("a".to_string()),
+            RispExp::Symbol("b".to_string()),
+        ])));
+
+        let expr = r#"(f "never closed)"#;
+        assert_eq!(parse(expr), Err(RispErr::Parse(
+            "unterminated string literal".to_string(),
+            Span { offset: 3, len: 14, line: 1, col: 4 },
+        )));
+
+        let expr = r#""bad \q escape""#;
+        assert_eq!(parse(expr), Err(RispErr::Parse(
+            r"unknown escape `\q`".to_string(),
+            Span { offset: 0, len: 15, line: 1, col: 1 },
+        )));
+
+        for expr in [r#""\u{110000}""#, r#""\u1234""#, r#""\u{zz}""#, r#""trailing\"#].iter() {
+            assert!(parse(expr).is_err(), "{:?} should not parse", expr);
+        }
+
+        let s = RispExp::Str("line\n\"quote\"".to_string());
+        assert_eq!(parse(&s.to_string()), Ok(s));
+    }
+
+    #[test]
+    fn test_comments() {
+        let expr = "; leading comment\n(+ 1 ; one\n   2) ; trailing comment";
+        assert_eq!(tokenize(expr), vec!["(", "+", "1", "2", ")"]);
+        assert_eq!(tokenize(expr)[3].span, Span { offset: 32, len: 1, line: 3, col: 4 });
+
+        let expr = "(+ 1 #| block #| nested |# still comment |# 2)";
+        assert_eq!(parse(expr), parse("(+ 1 2)"));
+
+        let expr = "(list 1 #;(ignored (entirely)) 2 #; 3)";
+        assert_eq!(parse(expr), parse("(list 1 2)"));
+
+        let expr = "#;skipped kept";
+        assert_eq!(parse(expr), Ok(RispExp::Symbol("kept".to_string())));
+
+        let expr = "(a;comment\nb)";
+        assert_eq!(parse(expr), parse("(a b)"));
+
+        let expr = r#"(f "; not a comment #| either |#")"#;
+        assert_eq!(parse(expr), Ok(RispExp::List(vec![
+            RispExp::Symbol("f".to_string()),
+            RispExp::Str("; not a comment #| either |#".to_string()),
+        ])));
+
+        // Comments at EOF
+        assert_eq!(parse("(f) ; done"), parse("(f)"));
+        assert_eq!(parse("(f) #| done |#"), parse("(f)"));
+        assert_eq!(parse("(f ; unclosed"), Err(RispErr::Parse(
+            "unbalanced parentheses: `(` is never closed".to_string(),
+            Span { offset: 0, len: 1, line: 1, col: 1 },
+        )));
+        assert_eq!(parse("(f #| unclosed"), Err(RispErr::Parse(
+            "unterminated block comment".to_string(),
+            Span { offset: 3, len: 2, line: 1, col: 4 },
+        )));
+        assert_eq!(parse("(f #;)"), Err(RispErr::Parse(
+            "unexpected `)`".to_string(),
+            Span { offset: 5, len: 1, line: 1, col: 6 },
+        )));
+        assert!(parse("#;").is_err());
+
+        // Datum comments don't throw off the source map
+        let (_, map) = parse_spanned("(a #;(b c) d)").expect("failed to parse");
+        assert_eq!(map.find(&RispExp::Symbol("d".to_string())), Some(Span { offset: 11, len: 1, line: 1, col: 12 }));
+    }
+
+    #[test]
+    fn test_parse_all() {
+        let program = "(let r 10)\n; area\n(* pi (* r r)) #;(ignored) 42";
+        let forms = parse_all(program).expect("failed to parse");
+        assert_eq!(forms, vec![
+            parse("(let r 10)").expect("failed to parse"),
+            parse("(* pi (* r r))").expect("failed to parse"),
+            RispExp::Number(42.0),
+        ]);
+
+        let forms = parse_all_spanned(program).expect("failed to parse");
+        assert_eq!(forms[1].1.root_span(), Span { offset: 18, len: 14, line: 3, col: 1 });
+        assert_eq!(forms[2].1.root_span(), Span { offset: 45, len: 2, line: 3, col: 28 });
+
+        assert_eq!(parse_all(""), Ok(vec![]));
+        assert_eq!(parse_all("; nothing here"), Ok(vec![]));
+        assert!(parse_all("(a) (b").is_err());
+
+        assert_eq!(parse("1 2"), Err(RispErr::Parse(
+            "unexpected input after the expression".to_string(),
+            Span { offset: 2, len: 1, line: 1, col: 3 },
+        )));
+        assert_eq!(parse("1 #;2"), Ok(RispExp::Number(1.0)));
+    }
+
+    #[test]
+    fn test_run_program() {
+        let mut env = standard_env();
+        let program = "
+            (let r 10)
+            (let area (fn (r) (* pi (* r r))))
+            (area r)
+        ";
+        let output = run_program(program, &mut env).expect("failed to run");
+        assert_eq!(output, RispExp::Number(std::f64::consts::PI * 100.0));
+
+        assert_eq!(run_program("", &mut env), Ok(RispExp::List(vec![])));
+        assert_eq!(run_program("(let x 1) (+ x", &mut env), Err(RispErr::Parse(
+            "unbalanced parentheses: `(` is never closed".to_string(),
+            Span { offset: 10, len: 1, line: 1, col: 11 },
+        )));
+        // Nothing runs if the program doesn't parse
+        assert_eq!(env.get("x"), None);
+    }
+
+    #[test]
+    fn test_shebang() {
+        let program = "#!/usr/bin/env repl\n(+ 1 2)";
+        assert_eq!(parse_all(program), Ok(vec![parse("(+ 1 2)").expect("failed to parse")]));
+        assert_eq!(tokenize(program)[0].span, Span { offset: 20, len: 1, line: 2, col: 1 });
+
+        // Only on the first line
+        assert!(parse_all("(+ 1 2)\n#!not-a-shebang").is_ok());
+        assert_eq!(parse_all("(+ 1 2)\n#!not-a-shebang").map(|v| v.len()), Ok(2));
+    }
+
+    #[test]
+    fn test_is_incomplete() {
+        for program in ["(", "(let f (fn (x)\n", r#"(f "open"#, r#""escaped \""#, "(f) #| open", "#;", "(a (b) c"].iter() {
+            assert!(is_incomplete(program), "{:?} should be incomplete", program);
+        }
+        for program in ["", "(f)", "(f))", r#""done""#, r#""\\""#, "(f) #| closed |#", "(f ; (\n)", "1 2 3"].iter() {
+            assert!(!is_incomplete(program), "{:?} should be complete", program);
+        }
+    }
+
+    /// A random value made only of things the reader can produce.
+    fn random_exp(rng: &mut rand::rngs::StdRng, depth: usize) -> RispExp {
+        use rand::Rng;
+        use rand::seq::SliceRandom;
+
+        let kind = if depth == 0 { rng.gen_range(0..4) } else { rng.gen_range(0..5) };
+        match kind {
+            0 => RispExp::Bool(rng.gen()),
+            1 => {
+                let n = match rng.gen_range(0..4) {
+                    0 => rng.gen_range(-1000..1000) as f64,
+                    1 => rng.gen::<f64>(),
+                    2 => rng.gen_range(-1.0..1.0) * 10f64.powi(rng.gen_range(-30..30)),
+                    _ => f64::from_bits(rng.gen::<u64>()),
+                };
+                // NaN never equals itself, and isn't interesting here
+                RispExp::Number(if n.is_nan() { 0.0 } else { n })
+            },
+            2 => {
+                let first = b"abcxyz+-*/<=>!?";
+                let rest = b"abcxyz0123456789+-*/<=>!?.";
+                let mut sym = (*first.choose(rng).unwrap() as char).to_string();
+                for _ in 0..rng.gen_range(0..6) {
+                    sym.push(*rest.choose(rng).unwrap() as char);
+                }
+                // Anything that reads as a number or bool isn't a symbol
+                if parse_atom(&sym) != RispExp::Symbol(sym.clone()) {
+                    sym.insert(0, 'x');
+                }
+                RispExp::Symbol(sym)
+            },
+            3 => {
+                let chars = ['a', 'Z', ' ', '"', '\\', '\n', '\t', '\r', '\0', ';', '(', ')', '#', '|', '\'', 'é', '\u{7f}', '\u{301}', '字', '🦀'];
+                let s = (0..rng.gen_range(0..8)).map(|_| *chars.choose(rng).unwrap()).collect();
+                RispExp::Str(s)
+            },
+            _ => {
+                let len = rng.gen_range(0..5);
+                RispExp::List((0..len).map(|_| random_exp(rng, depth - 1)).collect())
+            },
+        }
+    }
+
+    #[test]
+    fn test_display_round_trips() {
+        use rand::SeedableRng;
+
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0x5eed);
+        for _ in 0..2000 {
+            let exp = random_exp(&mut rng, 3);
+            let printed = exp.to_string();
+            assert_eq!(parse(&printed), Ok(exp), "{} didn't round trip", printed);
+        }
+    }
+
+    #[test]
+    fn test_display() {
+        let exp = parse("(1 (2.5 \"a b\") sym true ())").expect("failed to parse");
+        assert_eq!(exp.to_string(), "(1 (2.5 \"a b\") sym true ())");
+        assert_eq!(RispExp::Number(1e300).to_string(), "1e300");
+        assert_eq!(RispExp::Number(-2.5e-9).to_string(), "-2.5e-9");
+        assert_eq!(RispExp::Number(0.25).to_string(), "0.25");
+
+        let mut env = standard_env();
+        let lambda = run_program("(fn (x y) (+ x (* y 2)))", &mut env).expect("failed to eval");
+        assert_eq!(lambda.to_string(), "(fn (x y) (+ x (* y 2)))");
+        assert_eq!(run_program("+", &mut env).expect("failed to eval").to_string(), "#<builtin +>");
+
+        // A printed lambda evaluates to an equivalent lambda
+        let printed = format!("({} 1 2)", lambda);
+        assert_eq!(run_program(&printed, &mut env), Ok(RispExp::Number(5.0)));
+    }
 }
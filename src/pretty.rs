@@ -0,0 +1,173 @@
+//! Width aware printing of values for humans, e.g. REPL results.
+
+use std::borrow::Cow;
+
+use crate::RispExp;
+
+/// Lays values out over several lines when they don't fit in `width`
+/// columns, and cuts lists longer than `max_items` short with a count of
+/// what was left out. Unlike `Display`, elided output can't be read back.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PrettyPrinter {
+    pub width: usize,
+    pub max_items: usize,
+}
+
+impl Default for PrettyPrinter {
+    fn default() -> Self {
+        Self { width: 80, max_items: 100 }
+    }
+}
+
+impl PrettyPrinter {
+    pub fn print(&self, exp: &RispExp) -> String {
+        let mut out = String::new();
+        self.write(exp, 0, &mut out);
+        out
+    }
+
+    /// The elements of a list, or of a lambda printed as `(fn (...) body)`.
+    /// Only lambdas need building, lists are borrowed.
+    fn elements(exp: &RispExp) -> Option<Cow<'_, [RispExp]>> {
+        match exp {
+            RispExp::List(v) => Some(Cow::Borrowed(v)),
+            RispExp::Lambda(l) => {
+                let params = l.params.iter().map(|p| RispExp::Symbol(p.clone())).collect();
+                let fn_form = vec![RispExp::Symbol("fn".to_string()), RispExp::List(params), *l.body.clone()];
+                Some(Cow::Owned(fn_form))
+            },
+            _ => None,
+        }
+    }
+
+    /// Whether `exp` prints as a parenthesized form.
+    fn is_compound(exp: &RispExp) -> bool {
+        matches!(exp, RispExp::List(_) | RispExp::Lambda(_))
+    }
+
+    /// The one line rendering of each element, ending with a `... N more`
+    /// marker if the list is too long.
+    fn flat_items(&self, items: &[RispExp]) -> Vec<String> {
+        let shown = items.len().min(self.max_items);
+        let mut flat: Vec<_> = items[..shown].iter().map(|x| self.flat(x)).collect();
+        if items.len() > shown {
+            flat.push(format!("... {} more", items.len() - shown));
+        }
+        flat
+    }
+
+    fn flat(&self, exp: &RispExp) -> String {
+        match Self::elements(exp) {
+            Some(items) => format!("({})", self.flat_items(&items).join(" ")),
+            None => exp.to_string(),
+        }
+    }
+
+    /// Write `exp` starting at column `col`.
+    fn write(&self, exp: &RispExp, col: usize, out: &mut String) {
+        let flat = self.flat(exp);
+        let items = match Self::elements(exp) {
+            Some(items) if col + flat.chars().count() > self.width && !items.is_empty() => items,
+            _ => {
+                out.push_str(&flat);
+                return;
+            },
+        };
+
+        let indent = col + 1;
+        out.push('(');
+        let shown = items.len().min(self.max_items);
+        if !items.iter().any(Self::is_compound) {
+            // Plain data, fill each line with as many items as fit
+            let mut line_col = indent;
+            for (i, item) in self.flat_items(&items).iter().enumerate() {
+                let len = item.chars().count();
+                if i > 0 {
+                    if line_col + 1 + len > self.width {
+                        newline(out, indent);
+                        line_col = indent;
+                    } else {
+                        out.push(' ');
+                        line_col += 1;
+                    }
+                }
+                out.push_str(item);
+                line_col += len;
+            }
+        } else {
+            // Nested structure, one element per line. A symbol in operator
+            // position keeps its first argument beside it, the rest line up
+            // under that argument.
+            let (start, indent) = match &items[0] {
+                RispExp::Symbol(head) if shown > 1 => {
+                    out.push_str(head);
+                    out.push(' ');
+                    (1, indent + head.chars().count() + 1)
+                },
+                _ => (0, indent),
+            };
+            for (i, item) in items[start..shown].iter().enumerate() {
+                if i > 0 {
+                    newline(out, indent);
+                }
+                self.write(item, indent, out);
+            }
+            if items.len() > shown {
+                newline(out, indent);
+                out.push_str(&format!("... {} more", items.len() - shown));
+            }
+        }
+        out.push(')');
+    }
+}
+
+fn newline(out: &mut String, indent: usize) {
+    out.push('\n');
+    out.push_str(&" ".repeat(indent));
+}
+
+impl RispExp {
+    /// Pretty print with the default settings and the given width.
+    pub fn pretty(&self, width: usize) -> String {
+        PrettyPrinter { width, ..PrettyPrinter::default() }.print(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn test_pretty_fits() {
+        let exp = parse("(1 (2 3) \"four\")").expect("failed to parse");
+        assert_eq!(exp.pretty(80), exp.to_string());
+        assert_eq!(RispExp::Number(1.0).pretty(0), "1");
+    }
+
+    #[test]
+    fn test_pretty_breaks_lines() {
+        let exp = parse("(let f (fn (x) (+ x 100000)))").expect("failed to parse");
+        assert_eq!(exp.pretty(24), "(let f\n     (fn (x)\n         (+ x 100000)))");
+
+        let exp = parse("(10 20 30 40 50 60)").expect("failed to parse");
+        assert_eq!(exp.pretty(10), "(10 20 30\n 40 50 60)");
+
+        // Every line stays within the width when the atoms allow it
+        let exp = parse("((1 2 3 4) (5 6 7 8) ((9 10) (11 12)))").expect("failed to parse");
+        let printed = exp.pretty(12);
+        assert!(printed.lines().all(|l| l.chars().count() <= 12), "{}", printed);
+        assert_eq!(parse(&printed), Ok(exp));
+    }
+
+    #[test]
+    fn test_pretty_elides() {
+        let samples = RispExp::List((0..4096).map(|i| RispExp::Number(i as f64)).collect());
+        let printed = samples.pretty(80);
+        assert!(printed.ends_with(" ... 3996 more)"), "{}", printed);
+        assert!(printed.lines().all(|l| l.chars().count() <= 80));
+
+        let printer = PrettyPrinter { width: 80, max_items: 2 };
+        let exp = parse("(1 2 3 4)").expect("failed to parse");
+        assert_eq!(printer.print(&exp), "(1 2 ... 2 more)");
+    }
+}